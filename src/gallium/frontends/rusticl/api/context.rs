@@ -51,6 +51,8 @@ pub fn create_context(
 
     // CL_INVALID_PROPERTY [...] if the same property name is specified more than once.
     let props = Properties::from_ptr(properties).ok_or(CL_INVALID_PROPERTY)?;
+    let mut gl_context = None;
+    let mut gl_display = None;
     for p in props.props {
         match p.0 as u32 {
             // CL_INVALID_PLATFORM [...] if platform value specified in properties is not a valid platform.
@@ -60,10 +62,22 @@ pub fn create_context(
             CL_CONTEXT_INTEROP_USER_SYNC => {
                 check_cl_bool(p.1).ok_or(CL_INVALID_PROPERTY)?;
             }
+            // cl_khr_gl_sharing: accept the GL context handle and whichever platform-specific
+            // display/share-group handle accompanies it. rusticl doesn't interpret either one
+            // here, it just has to stop rejecting them so `Context` can record them for the
+            // eventual clCreateFromGLBuffer/clCreateFromGLTexture.
+            CL_GL_CONTEXT_KHR => gl_context = Some(p.1 as usize),
+            CL_EGL_DISPLAY_KHR | CL_GLX_DISPLAY_KHR | CL_WGL_HDC_KHR | CL_CGL_SHAREGROUP_KHR => {
+                gl_display = Some(p.1 as usize)
+            }
             // CL_INVALID_PROPERTY if context property name in properties is not a supported property name
             _ => return Err(CL_INVALID_PROPERTY),
         }
     }
+    let gl_interop = gl_context.map(|gl_context| GlInterop {
+        gl_context: gl_context,
+        display: gl_display.unwrap_or(0),
+    });
 
     // Duplicate devices specified in devices are ignored.
     let set: HashSet<_> =
@@ -73,6 +87,9 @@ pub fn create_context(
     Ok(cl_context::from_arc(Context::new(
         devs?,
         Properties::from_ptr_raw(properties),
+        pfn_notify,
+        user_data,
+        gl_interop,
     )))
 }
 
@@ -85,8 +102,19 @@ pub fn create_context_from_type(
     // CL_INVALID_DEVICE_TYPE if device_type is not a valid value.
     check_cl_device_type(device_type)?;
 
+    // CL_CONTEXT_PLATFORM narrows device_type's search to a single platform, same as
+    // create_context validates it for an explicit device list.
+    let props = Properties::from_ptr(properties).ok_or(CL_INVALID_PROPERTY)?;
+    let plat = props
+        .props
+        .iter()
+        .find(|p| p.0 as u32 == CL_CONTEXT_PLATFORM)
+        .map(|p| (p.1 as cl_platform_id).check().map(|_| p.1 as cl_platform_id))
+        .transpose()?;
+
     let devs: Vec<_> = get_devs_for_type(device_type)
         .iter()
+        .filter(|d| plat.map_or(true, |plat| d.platform == plat))
         .map(|d| d.cl)
         .collect();
 
@@ -0,0 +1,14 @@
+extern crate rusticl_opencl_gen;
+
+use crate::api::icd::*;
+use crate::api::util::*;
+use crate::core::event::*;
+
+use self::rusticl_opencl_gen::*;
+
+impl CLInfo<cl_profiling_info> for cl_event {
+    fn query(&self, q: cl_profiling_info) -> CLResult<Vec<u8>> {
+        let e = self.get_ref()?;
+        Ok(cl_prop::<cl_ulong>(e.profiling_info(q)?))
+    }
+}
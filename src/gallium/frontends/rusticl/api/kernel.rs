@@ -13,6 +13,7 @@ use self::mesa_rust_util::string::*;
 use self::rusticl_opencl_gen::*;
 
 use std::collections::HashSet;
+use std::mem;
 use std::ptr;
 use std::slice;
 use std::sync::Arc;
@@ -21,6 +22,7 @@ impl CLInfo<cl_kernel_info> for cl_kernel {
     fn query(&self, q: cl_kernel_info, _: &[u8]) -> CLResult<Vec<u8>> {
         let kernel = self.get_ref()?;
         Ok(match q {
+            CL_KERNEL_ATTRIBUTES => cl_prop::<&str>(&kernel.attributes_string),
             CL_KERNEL_CONTEXT => {
                 let ptr = Arc::as_ptr(&kernel.prog.context);
                 cl_prop::<cl_context>(cl_context::from_ptr(ptr))
@@ -64,10 +66,57 @@ impl CLInfoObj<cl_kernel_work_group_info, cl_device_id> for cl_kernel {
         let kernel = self.get_ref()?;
         let dev = dev.get_arc()?;
         Ok(match *q {
+            // the work-group size the kernel was compiled with via the `reqd_work_group_size`
+            // attribute, or all zeroes if it wasn't specified.
+            CL_KERNEL_COMPILE_WORK_GROUP_SIZE => cl_prop::<[usize; 3]>(kernel.work_group_size),
+            // only meaningful for custom devices or built-in kernels, neither of which we support.
+            CL_KERNEL_GLOBAL_WORK_SIZE => Err(CL_INVALID_VALUE)?,
             CL_KERNEL_LOCAL_MEM_SIZE => cl_prop::<cl_ulong>(kernel.local_mem_size(&dev)),
+            CL_KERNEL_PREFERRED_WORK_GROUP_SIZE_MULTIPLE => cl_prop::<usize>(dev.subgroup_size()),
             CL_KERNEL_PRIVATE_MEM_SIZE => cl_prop::<cl_ulong>(kernel.priv_mem_size(&dev)),
-            // TODO
-            CL_KERNEL_WORK_GROUP_SIZE => cl_prop::<usize>(1),
+            CL_KERNEL_WORK_GROUP_SIZE => cl_prop::<usize>(kernel.max_threads_per_block(&dev)),
+            // CL_INVALID_VALUE if param_name is not one of the supported values
+            _ => Err(CL_INVALID_VALUE)?,
+        })
+    }
+}
+
+impl CLInfoObj<cl_kernel_sub_group_info, (cl_device_id, &[u8])> for cl_kernel {
+    fn query(&self, o: (cl_device_id, &[u8]), q: cl_kernel_sub_group_info) -> CLResult<Vec<u8>> {
+        let (dev, input) = o;
+        let kernel = self.get_ref()?;
+        let dev = dev.get_arc()?;
+
+        // CL_KERNEL_MAX_SUB_GROUP_SIZE_FOR_NDRANGE and CL_KERNEL_SUB_GROUP_COUNT_FOR_NDRANGE carry
+        // a work-dim-sized local work size array in `input`.
+        let local_work_size = |input: &[u8]| -> CLResult<&[usize]> {
+            if input.is_empty() || input.len() % mem::size_of::<usize>() != 0 {
+                Err(CL_INVALID_VALUE)?;
+            }
+            Ok(unsafe {
+                slice::from_raw_parts(input.as_ptr().cast(), input.len() / mem::size_of::<usize>())
+            })
+        };
+
+        Ok(match *q {
+            // our sub-group width doesn't depend on the requested local work size.
+            CL_KERNEL_MAX_SUB_GROUP_SIZE_FOR_NDRANGE => {
+                local_work_size(input)?;
+                cl_prop::<usize>(dev.subgroup_size())
+            }
+            CL_KERNEL_SUB_GROUP_COUNT_FOR_NDRANGE => {
+                let local_work_size = local_work_size(input)?;
+                let work_group_size: usize = local_work_size.iter().product();
+                let sub_group_size = dev.subgroup_size();
+                cl_prop::<usize>((work_group_size + sub_group_size - 1) / sub_group_size)
+            }
+            CL_KERNEL_MAX_NUM_SUB_GROUPS => {
+                let sub_group_size = dev.subgroup_size();
+                let max_threads = kernel.max_threads_per_block(&dev);
+                cl_prop::<usize>((max_threads + sub_group_size - 1) / sub_group_size)
+            }
+            // we don't support an explicit `reqd_sub_group_size` kernel attribute yet.
+            CL_KERNEL_COMPILE_NUM_SUB_GROUPS => cl_prop::<usize>(0),
             // CL_INVALID_VALUE if param_name is not one of the supported values
             _ => Err(CL_INVALID_VALUE)?,
         })
@@ -83,6 +132,88 @@ fn checked_kernel_work_arr(arr: *const usize, work_dim: cl_uint) -> &'static [us
     }
 }
 
+// widens a `work_dim`-length slice to `[usize; 3]`, filling the unused high dimensions with `pad`.
+fn pad_work_arr(vals: &[usize], pad: usize) -> [usize; 3] {
+    let mut res = [pad; 3];
+    for (i, v) in vals.iter().enumerate() {
+        res[i] = *v;
+    }
+    res
+}
+
+// smallest prime factor of `n` (n > 1); used by `pick_local_work_size` to grow one work-group
+// dimension at a time by the smallest amount that keeps it an even divisor of the global size.
+fn smallest_prime_factor(n: usize) -> usize {
+    let mut f = 2;
+    while f * f <= n {
+        if n % f == 0 {
+            return f;
+        }
+        f += 1;
+    }
+    n
+}
+
+// Picks a local work-group size for an enqueue that left `local_work_size` unspecified. Starting
+// from `[1; 3]`, each pass walks the dimensions from 0 to 2 and greedily grows every one that
+// still evenly divides `global_work_size`, stays within the device's per-dimension limit and the
+// kernel's total CL_KERNEL_WORK_GROUP_SIZE, repeating until no dimension can grow any further.
+// Trying dimension 0 first keeps adjacent work-items -- and therefore their memory accesses --
+// coalesced.
+fn pick_local_work_size(global: [usize; 3], max_block: [usize; 3], max_threads: usize) -> [usize; 3] {
+    let mut local = [1usize; 3];
+    loop {
+        let mut grown = false;
+        for dim in 0..3 {
+            let remaining = global[dim] / local[dim];
+            if remaining <= 1 {
+                continue;
+            }
+
+            let candidate = local[dim] * smallest_prime_factor(remaining);
+            let total: usize = (0..3)
+                .map(|i| if i == dim { candidate } else { local[i] })
+                .product();
+
+            if candidate > max_block[dim] || total > max_threads || global[dim] % candidate != 0 {
+                continue;
+            }
+
+            local[dim] = candidate;
+            grown = true;
+        }
+
+        if !grown {
+            return local;
+        }
+    }
+}
+
+// Same as `pick_local_work_size`, but for devices advertising
+// CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT: since boundary work-groups no longer need to divide
+// `global` evenly (the driver masks off their out-of-range tail via `last_block`), just grow each
+// dimension greedily up to the device/kernel limits instead of hunting for an even divisor.
+fn pick_local_work_size_non_uniform(
+    global: [usize; 3],
+    max_block: [usize; 3],
+    max_threads: usize,
+) -> [usize; 3] {
+    let mut local = [1usize; 3];
+    for dim in 0..3 {
+        local[dim] = global[dim].min(max_block[dim]).max(1);
+    }
+
+    while local.iter().product::<usize>() > max_threads {
+        let dim = (0..3).filter(|&d| local[d] > 1).max_by_key(|&d| local[d]);
+        match dim {
+            Some(dim) => local[dim] -= 1,
+            None => break,
+        }
+    }
+
+    local
+}
+
 fn get_devices_with_valid_build(p: &Arc<Program>) -> CLResult<Vec<&Arc<Device>>> {
     // CL_INVALID_PROGRAM_EXECUTABLE if there is no successfully built executable for program.
     let devs: Vec<_> = p
@@ -131,6 +262,14 @@ pub fn create_kernel(
     )))
 }
 
+pub fn clone_kernel(source_kernel: cl_kernel) -> CLResult<cl_kernel> {
+    let k = source_kernel.get_arc()?;
+
+    // `Kernel`'s `Clone` impl already deep-copies `values`, so already-set `Constant`,
+    // `MemObject`, `LocalMem`, and `Sampler` arguments carry over to the clone.
+    Ok(cl_kernel::from_arc(Arc::new((*k).clone())))
+}
+
 pub fn create_kernels_in_program(
     program: cl_program,
     num_kernels: cl_uint,
@@ -258,6 +397,68 @@ pub fn set_kernel_arg(
     //• CL_MAX_SIZE_RESTRICTION_EXCEEDED if the size in bytes of the memory object (if the argument is a memory object) or arg_size (if the argument is declared with local qualifier) exceeds a language- specified maximum size restriction for this argument, such as the MaxByteOffset SPIR-V decoration. This error code is missing before version 2.2.
 }
 
+pub fn set_kernel_arg_svm_pointer(
+    kernel: cl_kernel,
+    arg_index: cl_uint,
+    arg_value: *const ::std::os::raw::c_void,
+) -> CLResult<()> {
+    let k = kernel.get_arc()?;
+
+    // CL_INVALID_ARG_INDEX if arg_index is not a valid argument index.
+    let arg = k.args.get(arg_index as usize).ok_or(CL_INVALID_ARG_INDEX)?;
+
+    // CL_INVALID_ARG_VALUE if arg_value is NULL.
+    if arg_value.is_null() {
+        Err(CL_INVALID_ARG_VALUE)?;
+    }
+
+    // CL_INVALID_ARG_VALUE if the argument at arg_index is not a pointer argument.
+    if arg.kind != KernelArgType::MemGlobal && arg.kind != KernelArgType::MemConstant {
+        Err(CL_INVALID_ARG_VALUE)?;
+    }
+
+    // unlike `set_kernel_arg`, there's no `sizeof(cl_mem)` to check against: SVM pointers are
+    // passed straight through as raw device addresses.
+    k.values
+        .get(arg_index as usize)
+        .unwrap()
+        .replace(Some(KernelArgValue::Svm(arg_value as usize)));
+    Ok(())
+}
+
+pub fn set_kernel_exec_info(
+    kernel: cl_kernel,
+    param_name: cl_kernel_exec_info,
+    param_value_size: usize,
+    param_value: *const ::std::os::raw::c_void,
+) -> CLResult<()> {
+    let k = kernel.get_arc()?;
+
+    // CL_INVALID_VALUE if param_value is NULL.
+    if param_value.is_null() {
+        Err(CL_INVALID_VALUE)?;
+    }
+
+    match param_name {
+        CL_KERNEL_EXEC_INFO_SVM_PTRS => {
+            // CL_INVALID_VALUE if param_value_size is not a multiple of sizeof(void *).
+            if param_value_size % std::mem::size_of::<usize>() != 0 {
+                Err(CL_INVALID_VALUE)?;
+            }
+            let count = param_value_size / std::mem::size_of::<usize>();
+            let ptrs = unsafe { slice::from_raw_parts(param_value.cast::<usize>(), count) };
+            k.svm_ptrs.replace(ptrs.to_vec());
+        }
+        CL_KERNEL_EXEC_INFO_SVM_FINE_GRAIN_SYSTEM => {
+            let enabled = unsafe { *param_value.cast::<cl_bool>() } != 0;
+            k.svm_fine_grain_system.set(enabled);
+        }
+        // CL_INVALID_VALUE if param_name is not one of the supported values
+        _ => Err(CL_INVALID_VALUE)?,
+    }
+    Ok(())
+}
+
 pub fn enqueue_ndrange_kernel(
     command_queue: cl_command_queue,
     kernel: cl_kernel,
@@ -289,12 +490,23 @@ pub fn enqueue_ndrange_kernel(
         Err(CL_INVALID_KERNEL_ARGS)?
     }
 
+    // CL_INVALID_OPERATION if SVM pointers are passed as arguments to a kernel and the device does
+    // not support SVM.
+    if !q.device.svm_supported()
+        && k.values
+            .iter()
+            .any(|v| matches!(v.borrow().as_ref(), Some(KernelArgValue::Svm(_))))
+    {
+        Err(CL_INVALID_OPERATION)?;
+    }
+
     // CL_INVALID_WORK_DIMENSION if work_dim is not a valid value (i.e. a value between 1 and
     // CL_DEVICE_MAX_WORK_ITEM_DIMENSIONS).
     if work_dim == 0 || work_dim > q.device.max_grid_dimensions() {
         Err(CL_INVALID_WORK_DIMENSION)?;
     }
 
+    let local_work_size_given = !local_work_size.is_null();
     let global_work_size = checked_kernel_work_arr(global_work_size, work_dim);
     let local_work_size = checked_kernel_work_arr(local_work_size, work_dim);
     let global_work_offset = checked_kernel_work_arr(global_work_offset, work_dim);
@@ -324,6 +536,56 @@ pub fn enqueue_ndrange_kernel(
         Err(CL_INVALID_WORK_ITEM_SIZE)?;
     }
 
+    let max_block = q.device.max_block_sizes();
+    let max_threads = k.max_threads_per_block(&q.device);
+    // a non-zero CL_KERNEL_COMPILE_WORK_GROUP_SIZE means the kernel source pinned the work-group
+    // size via `reqd_work_group_size`, in which case the enqueue's work-group size must be uniform.
+    let reqd_work_group_size = k.work_group_size;
+    let uniform_reqd = reqd_work_group_size != ZERO_ARR;
+    // `reqd_work_group_size` kernels still require a uniform partition even on devices that
+    // otherwise support CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT.
+    let non_uniform_allowed = !uniform_reqd && q.device.non_uniform_work_group_support();
+
+    let resolved_local_work_size = if local_work_size_given {
+        let local = pad_work_arr(local_work_size, 1);
+
+        // CL_INVALID_WORK_GROUP_SIZE if the work-group size must be uniform and local_work_size is
+        // not equal to the required work-group size specified in the kernel source.
+        if uniform_reqd && local != reqd_work_group_size {
+            Err(CL_INVALID_WORK_GROUP_SIZE)?;
+        }
+
+        // CL_INVALID_WORK_GROUP_SIZE if local_work_size is specified and the total number of
+        // work-items in the work-group computed as local_work_size[0] × … local_work_size[work_dim
+        // - 1] is greater than the value specified by CL_KERNEL_WORK_GROUP_SIZE.
+        if local.iter().product::<usize>() > max_threads {
+            Err(CL_INVALID_WORK_GROUP_SIZE)?;
+        }
+
+        // CL_INVALID_WORK_GROUP_SIZE if the work-group size must be uniform and the
+        // global_work_size is not evenly divisible by the local_work_size. Devices advertising
+        // CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT allow a partial, driver-masked boundary
+        // work-group instead (see `Kernel::launch`'s `last_block`).
+        if !non_uniform_allowed {
+            for i in 0..work_dim as usize {
+                if global_work_size[i] % local[i] != 0 {
+                    Err(CL_INVALID_WORK_GROUP_SIZE)?;
+                }
+            }
+        }
+
+        local
+    } else if uniform_reqd {
+        reqd_work_group_size
+    } else {
+        let global = pad_work_arr(global_work_size, 1);
+        if non_uniform_allowed {
+            pick_local_work_size_non_uniform(global, max_block, max_threads)
+        } else {
+            pick_local_work_size(global, max_block, max_threads)
+        }
+    };
+
     // If global_work_size is NULL, or the value in any passed dimension is 0 then the kernel
     // command will trivially succeed after its event dependencies are satisfied and subsequently
     // update its completion event.
@@ -333,7 +595,7 @@ pub fn enqueue_ndrange_kernel(
         k.launch(
             &q,
             work_dim,
-            local_work_size,
+            &resolved_local_work_size[..work_dim as usize],
             global_work_size,
             global_work_offset,
         )
@@ -343,16 +605,12 @@ pub fn enqueue_ndrange_kernel(
     cl_event::leak_ref(event, &e);
     q.queue(&e);
 
-    //• CL_INVALID_WORK_GROUP_SIZE if local_work_size is specified and does not match the required work-group size for kernel in the program source.
     //• CL_INVALID_WORK_GROUP_SIZE if local_work_size is specified and is not consistent with the required number of sub-groups for kernel in the program source.
-    //• CL_INVALID_WORK_GROUP_SIZE if local_work_size is specified and the total number of work-items in the work-group computed as local_work_size[0] × … local_work_size[work_dim - 1] is greater than the value specified by CL_KERNEL_WORK_GROUP_SIZE in the Kernel Object Device Queries table.
-    //• CL_INVALID_WORK_GROUP_SIZE if the work-group size must be uniform and the local_work_size is not NULL, is not equal to the required work-group size specified in the kernel source, or the global_work_size is not evenly divisible by the local_work_size.
     //• CL_MISALIGNED_SUB_BUFFER_OFFSET if a sub-buffer object is specified as the value for an argument that is a buffer object and the offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue. This error code
     //• CL_INVALID_IMAGE_SIZE if an image object is specified as an argument value and the image dimensions (image width, height, specified or compute row and/or slice pitch) are not supported by device associated with queue.
     //• CL_IMAGE_FORMAT_NOT_SUPPORTED if an image object is specified as an argument value and the image format (image channel order and data type) is not supported by device associated with queue.
     //• CL_OUT_OF_RESOURCES if there is a failure to queue the execution instance of kernel on the command-queue because of insufficient resources needed to execute the kernel. For example, the explicitly specified local_work_size causes a failure to execute the kernel because of insufficient resources such as registers or local memory. Another example would be the number of read-only image args used in kernel exceed the CL_DEVICE_MAX_READ_IMAGE_ARGS value for device or the number of write-only and read-write image args used in kernel exceed the CL_DEVICE_MAX_READ_WRITE_IMAGE_ARGS value for device or the number of samplers used in kernel exceed CL_DEVICE_MAX_SAMPLERS for device.
     //• CL_MEM_OBJECT_ALLOCATION_FAILURE if there is a failure to allocate memory for data store associated with image or buffer objects specified as arguments to kernel.
-    //• CL_INVALID_OPERATION if SVM pointers are passed as arguments to a kernel and the device does not support SVM or if system pointers are passed as arguments to a kernel and/or stored inside SVM allocations passed as kernel arguments and the device does not support fine grain system SVM allocations.
     Ok(())
 }
 
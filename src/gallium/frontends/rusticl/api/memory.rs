@@ -8,6 +8,7 @@ use crate::api::types::*;
 use crate::api::util::*;
 use crate::core::device::*;
 use crate::core::event::*;
+use crate::core::format::*;
 use crate::core::memory::*;
 use crate::*;
 
@@ -15,8 +16,10 @@ use self::mesa_rust_util::ptr::*;
 use self::rusticl_opencl_gen::*;
 
 use std::cmp::Ordering;
+use std::ffi::CString;
 use std::os::raw::c_void;
 use std::ptr;
+use std::slice;
 use std::sync::Arc;
 
 fn validate_mem_flags(flags: cl_mem_flags, images: bool) -> CLResult<()> {
@@ -31,7 +34,11 @@ fn validate_mem_flags(flags: cl_mem_flags, images: bool) -> CLResult<()> {
                 | CL_MEM_COPY_HOST_PTR
                 | CL_MEM_HOST_WRITE_ONLY
                 | CL_MEM_HOST_READ_ONLY
-                | CL_MEM_HOST_NO_ACCESS,
+                | CL_MEM_HOST_NO_ACCESS
+                // only meaningful to clSVMAlloc, but accepted here too since it shares this
+                // validation with clCreateBuffer's flags.
+                | CL_MEM_SVM_FINE_GRAIN_BUFFER
+                | CL_MEM_SVM_ATOMICS,
         );
     }
 
@@ -194,6 +201,11 @@ impl CLInfo<cl_mem_info> for cl_mem {
             CL_MEM_REFERENCE_COUNT => cl_prop::<cl_uint>(self.refcnt()?),
             CL_MEM_SIZE => cl_prop::<usize>(mem.size),
             CL_MEM_TYPE => cl_prop::<cl_mem_object_type>(mem.mem_type),
+            CL_MEM_USES_SVM_POINTER => {
+                let uses_svm = !mem.host_ptr.is_null()
+                    && mem.context.find_svm_alloc(mem.host_ptr as usize).is_some();
+                cl_prop::<bool>(uses_svm)
+            }
             _ => Err(CL_INVALID_VALUE)?,
         })
     }
@@ -264,7 +276,11 @@ pub fn create_sub_buffer(
 
             // CL_INVALID_VALUE if the region specified by the cl_buffer_region structure passed in
             // buffer_create_info is out of bounds in buffer.
-            if region.origin + region.size > b.size {
+            let region_end = region
+                .origin
+                .checked_add(region.size)
+                .ok_or(CL_INVALID_VALUE)?;
+            if region_end > b.size {
                 Err(CL_INVALID_VALUE)?
             }
 
@@ -274,14 +290,46 @@ pub fn create_sub_buffer(
         _ => Err(CL_INVALID_VALUE)?,
     };
 
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if there are no devices in context associated with buffer
+    // for which the origin field of the cl_buffer_region structure passed in buffer_create_info
+    // is aligned to the CL_DEVICE_MEM_BASE_ADDR_ALIGN value. That value is reported in bits, so
+    // convert to bytes, and require alignment against the largest one so the sub-buffer is safe
+    // to use on every device in the context, not just some of them.
+    let addr_align = b
+        .context
+        .devs
+        .iter()
+        .map(|d| d.mem_base_addr_align() as usize / 8)
+        .max()
+        .unwrap_or(0);
+    if addr_align == 0 || offset % addr_align != 0 {
+        Err(CL_MISALIGNED_SUB_BUFFER_OFFSET)?
+    }
+
     Ok(cl_mem::from_arc(Mem::new_sub_buffer(
         &b, flags, offset, size,
     )))
+}
 
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if there are no devices in context associated with buffer for which the origin field of the cl_buffer_region structure passed in buffer_create_info is aligned to the CL_DEVICE_MEM_BASE_ADDR_ALIGN value.
+// CL_MISALIGNED_SUB_BUFFER_OFFSET if buf is a sub-buffer object and the offset specified when it
+// was created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN (reported in bits, hence the /8) for
+// `dev`. `create_sub_buffer` above already rejects an offset that's misaligned for every device in
+// the context at creation time, but each of `dev`'s enqueue entry points has to re-check against
+// its own queue's device: SwiftShader's `isSubBufferAligned` performs the same gate.
+fn check_sub_buffer_alignment(buf: &Mem, dev: &Device) -> CLResult<()> {
+    if buf.parent.is_some() && buf.offset != 0 {
+        let addr_align = dev.mem_base_addr_align() as usize / 8;
+        if addr_align == 0 || buf.offset % addr_align != 0 {
+            Err(CL_MISALIGNED_SUB_BUFFER_OFFSET)?
+        }
+    }
+
+    Ok(())
 }
 
+// Registers a callback fired from `Mem`'s `Drop` impl once `memobj`'s last reference is released,
+// in LIFO order against every other callback registered on the same object -- see the `cbs` field
+// on `Mem` for where the stack lives and where it gets unwound.
 pub fn set_mem_object_destructor_callback(
     memobj: cl_mem,
     pfn_notify: Option<MemCB>,
@@ -301,6 +349,149 @@ pub fn set_mem_object_destructor_callback(
     Ok(())
 }
 
+// Coarse-grained SVM: a single host allocation registered with `context` so later calls (mem
+// object creation, kernel args, `CL_MEM_USES_SVM_POINTER`) can recognize a raw pointer as SVM
+// without the caller tracking its extent. Failures return NULL rather than a `CLResult`, matching
+// `clSVMAlloc`'s own signature.
+pub fn svm_alloc(
+    context: cl_context,
+    flags: cl_svm_mem_flags,
+    size: usize,
+    alignment: cl_uint,
+) -> *mut c_void {
+    let c = match context.get_arc() {
+        Ok(c) => c,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    // CL_INVALID_VALUE if values specified in flags are not valid, as described in the table for
+    // clSVMAlloc, or if size is 0.
+    if size == 0 || validate_mem_flags(flags, false).is_err() {
+        return ptr::null_mut();
+    }
+
+    let fine_grain = bit_check(flags, CL_MEM_SVM_FINE_GRAIN_BUFFER);
+    let atomics = bit_check(flags, CL_MEM_SVM_ATOMICS);
+
+    // CL_MEM_SVM_ATOMICS ... CL_INVALID_VALUE ... if this flag is specified and CL_MEM_SVM_FINE_GRAIN_BUFFER
+    // is not specified. Coarse/fine-grain SVM support must also actually be advertised by every
+    // device in the context via CL_DEVICE_SVM_CAPABILITIES.
+    if atomics && !fine_grain {
+        return ptr::null_mut();
+    }
+    for dev in &c.devs {
+        let caps = dev.svm_capabilities();
+        let supported = caps & CL_DEVICE_SVM_COARSE_GRAIN_BUFFER != 0
+            && (!fine_grain || caps & CL_DEVICE_SVM_FINE_GRAIN_BUFFER != 0)
+            && (!atomics || caps & CL_DEVICE_SVM_ATOMICS != 0);
+        if !supported {
+            return ptr::null_mut();
+        }
+    }
+
+    // alignment ... 0 ... means the returned pointer is guaranteed to have the alignment that's
+    // appropriate for the largest built-in data type for the context's devices.
+    let alignment = if alignment == 0 {
+        // long16/double16: 16 × 8 bytes
+        128
+    } else {
+        alignment as usize
+    };
+    if !alignment.is_power_of_two() {
+        return ptr::null_mut();
+    }
+
+    let layout = match std::alloc::Layout::from_size_align(size, alignment) {
+        Ok(layout) => layout,
+        Err(_) => return ptr::null_mut(),
+    };
+
+    let ptr = unsafe { std::alloc::alloc(layout) };
+    if ptr.is_null() {
+        return ptr::null_mut();
+    }
+
+    c.add_svm_ptr(ptr as usize, size, alignment);
+    ptr.cast()
+}
+
+pub fn svm_free(context: cl_context, svm_pointer: *mut c_void) {
+    let c = match context.get_ref() {
+        Ok(c) => c,
+        Err(_) => return,
+    };
+
+    if let Some((size, alignment)) = c.remove_svm_ptr(svm_pointer as usize) {
+        // Safe to unwrap: these are the exact size/alignment `svm_alloc` used to build the
+        // `Layout` it allocated this pointer with.
+        let layout = std::alloc::Layout::from_size_align(size, alignment).unwrap();
+        unsafe { std::alloc::dealloc(svm_pointer.cast(), layout) };
+    }
+}
+
+// `clEnqueueSVMFree`: ordered like any other enqueued command, so it only runs once whatever used
+// `svm_pointers` (per `event_wait_list`) has finished. With no `pfn_free_func`, it's just
+// `svm_free` for every pointer; with one, freeing is entirely the callback's responsibility -- we
+// just hand it the pointers, matching `clEnqueueSVMFree`'s own contract.
+pub fn enqueue_svm_free(
+    command_queue: cl_command_queue,
+    num_svm_pointers: cl_uint,
+    svm_pointers: *mut *mut c_void,
+    pfn_free_func: Option<SVMFreeCb>,
+    user_data: *mut c_void,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = command_queue.get_arc()?;
+    let evs = event_list_from_cl(num_events_in_wait_list, event_wait_list)?;
+
+    // CL_INVALID_VALUE if num_svm_pointers is 0 and svm_pointers is not NULL or if num_svm_pointers
+    // is not 0 and svm_pointers is NULL, as described for clEnqueueSVMFree. num_svm_pointers == 0
+    // with svm_pointers == NULL is a valid no-op call.
+    if (num_svm_pointers == 0) != svm_pointers.is_null() {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    let pointers: Vec<usize> = if svm_pointers.is_null() {
+        Vec::new()
+    } else {
+        unsafe { slice::from_raw_parts(svm_pointers, num_svm_pointers as usize) }
+            .iter()
+            .map(|&p| p as usize)
+            .collect()
+    };
+
+    let context = cl_context::from_arc(q.context.clone());
+    let e = Event::new(
+        &q,
+        CL_COMMAND_SVM_FREE,
+        evs,
+        Box::new(move |q| {
+            let mut ptrs: Vec<*mut c_void> = pointers.iter().map(|&p| p as *mut c_void).collect();
+            match pfn_free_func {
+                Some(cb) => unsafe {
+                    cb(
+                        cl_command_queue::from_arc(q.clone()),
+                        ptrs.len() as cl_uint,
+                        ptrs.as_mut_ptr(),
+                        user_data,
+                    )
+                },
+                None => {
+                    for &ptr in &ptrs {
+                        svm_free(context, ptr);
+                    }
+                }
+            }
+            Ok(())
+        }),
+    );
+    event.write_checked(cl_event::from_arc(e.clone()));
+    q.queue(&e);
+    Ok(())
+}
+
 fn validate_image_format<'a>(
     image_format: *const cl_image_format,
 ) -> CLResult<(&'a cl_image_format, u8)> {
@@ -435,22 +626,24 @@ fn validate_image_desc<'a>(
     }
 
     // mem_object may refer to a valid buffer or image memory object. mem_object can be a buffer
-    // memory object if image_type is CL_MEM_OBJECT_IMAGE1D_BUFFER or CL_MEM_OBJECT_IMAGE2D.
-    // mem_object can be an image object if image_type is CL_MEM_OBJECT_IMAGE2D. Otherwise it must
-    // be NULL.
-    //
-    // TODO: cl_khr_image2d_from_buffer is an optional feature
+    // memory object if image_type is CL_MEM_OBJECT_IMAGE1D_BUFFER or CL_MEM_OBJECT_IMAGE2D (the
+    // latter via cl_khr_image2d_from_buffer). mem_object can be an image object if image_type is
+    // CL_MEM_OBJECT_IMAGE2D. Otherwise it must be NULL.
     let p = unsafe { &desc.anon_1.mem_object };
-    if !p.is_null() {
-        let p = p.get_ref()?;
+    let mem_ref = if p.is_null() { None } else { Some(p.get_ref()?) };
+    if let Some(m) = mem_ref {
         if !match desc.image_type {
-            CL_MEM_OBJECT_IMAGE1D_BUFFER => p.is_buffer(),
-            CL_MEM_OBJECT_IMAGE2D => !p.is_buffer(),
+            CL_MEM_OBJECT_IMAGE1D_BUFFER => m.is_buffer(),
             _ => true,
         } {
             Err(CL_INVALID_OPERATION)?
         }
     }
+    // cl_khr_image2d_from_buffer: whether this is specifically the 2D-image-from-buffer case,
+    // which gets its row pitch computed below even without a host_ptr and gets the extra
+    // alignment checks in `validate_buffer`.
+    let buffer_backed_2d =
+        desc.image_type == CL_MEM_OBJECT_IMAGE2D && mem_ref.map_or(false, |m| m.is_buffer());
 
     // image_row_pitch is the scan-line pitch in bytes. This must be 0 if host_ptr is NULL and can
     // be either 0 or ≥ image_width × size of element in bytes if host_ptr is not NULL. If host_ptr
@@ -469,19 +662,35 @@ fn validate_image_desc<'a>(
     // image_row_pitch × image_height for a 2D image array or 3D image and image_row_pitch for a 1D
     // image array. If image_slice_pitch is not 0, it must be a multiple of the image_row_pitch.
     if host_ptr.is_null() {
-        if desc.image_row_pitch != 0 || desc.image_slice_pitch != 0 {
+        if buffer_backed_2d {
+            // cl_khr_image2d_from_buffer: the pitch still defaults to a tightly packed row when
+            // left at 0, exactly like the host_ptr case below does.
+            if desc.image_row_pitch == 0 {
+                desc.image_row_pitch = desc
+                    .image_width
+                    .checked_mul(elem_size)
+                    .ok_or(CL_INVALID_IMAGE_SIZE)?;
+            } else if desc.image_row_pitch % elem_size != 0 {
+                Err(err)?
+            }
+        } else if desc.image_row_pitch != 0 || desc.image_slice_pitch != 0 {
             Err(err)?
         }
     } else {
         if desc.image_row_pitch == 0 {
-            desc.image_row_pitch = desc.image_width * elem_size;
+            desc.image_row_pitch = desc
+                .image_width
+                .checked_mul(elem_size)
+                .ok_or(CL_INVALID_IMAGE_SIZE)?;
         } else if desc.image_row_pitch % elem_size != 0 {
             Err(err)?
         }
 
         if dims == 3 || array {
-            let valid_slice_pitch =
-                desc.image_row_pitch * if dims == 1 { 1 } else { desc.image_height };
+            let valid_slice_pitch = desc
+                .image_row_pitch
+                .checked_mul(if dims == 1 { 1 } else { desc.image_height })
+                .ok_or(CL_INVALID_IMAGE_SIZE)?;
             if desc.image_slice_pitch == 0 {
                 desc.image_slice_pitch = valid_slice_pitch;
             } else {
@@ -529,22 +738,67 @@ fn validate_buffer(
     if !mem_object.is_null() {
         let mem = mem_object.get_ref()?;
 
+        // CL_INVALID_VALUE if mem_object is specified and flags specify CL_MEM_USE_HOST_PTR,
+        // CL_MEM_ALLOC_HOST_PTR or CL_MEM_COPY_HOST_PTR: the image only ever aliases mem_object's
+        // own storage, so there's no host pointer of its own to use/allocate/copy from. Without
+        // this check a CL_MEM_COPY_HOST_PTR request would be silently dropped by `Mem::new_image`,
+        // which skips the upload entirely whenever a `source` is present.
+        if bit_check(
+            flags,
+            CL_MEM_USE_HOST_PTR | CL_MEM_ALLOC_HOST_PTR | CL_MEM_COPY_HOST_PTR,
+        ) {
+            Err(CL_INVALID_VALUE)?
+        }
+
         match mem.mem_type {
             CL_MEM_OBJECT_BUFFER => {
                 match desc.image_type {
                     // For a 1D image buffer created from a buffer object, the image_width × size of
                     // element in bytes must be ≤ size of the buffer object.
                     CL_MEM_OBJECT_IMAGE1D_BUFFER => {
-                        if desc.image_width * elem_size > mem.size {
+                        let bytes = desc
+                            .image_width
+                            .checked_mul(elem_size)
+                            .ok_or(CL_INVALID_IMAGE_SIZE)?;
+                        if bytes > mem.size {
                             Err(err)?
                         }
                     }
                     // For a 2D image created from a buffer object, the image_row_pitch × image_height
                     // must be ≤ size of the buffer object specified by mem_object.
                     CL_MEM_OBJECT_IMAGE2D => {
-                        //TODO
-                        //• CL_INVALID_IMAGE_FORMAT_DESCRIPTOR if a 2D image is created from a buffer and the row pitch and base address alignment does not follow the rules described for creating a 2D image from a buffer.
-                        if desc.image_row_pitch * desc.image_height > mem.size {
+                        // cl_khr_image2d_from_buffer: the row pitch (explicit, or computed by
+                        // `validate_image_desc` above) must be a multiple of the maximum
+                        // CL_DEVICE_IMAGE_PITCH_ALIGNMENT, in pixels, over all image-capable
+                        // devices in the context, and the buffer's own base offset must be a
+                        // multiple of CL_DEVICE_IMAGE_BASE_ADDRESS_ALIGNMENT for all of them.
+                        let pitch_alignment_px = mem
+                            .context
+                            .devs
+                            .iter()
+                            .filter(|d| d.image_supported())
+                            .map(|d| d.image_pitch_alignment() as usize)
+                            .max()
+                            .unwrap_or(0);
+                        let pitch_alignment = pitch_alignment_px
+                            .checked_mul(elem_size)
+                            .ok_or(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR)?;
+                        if pitch_alignment == 0 || desc.image_row_pitch % pitch_alignment != 0 {
+                            Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR)?
+                        }
+
+                        for dev in mem.context.devs.iter().filter(|d| d.image_supported()) {
+                            let addr_alignment = dev.image_base_address_alignment() as usize;
+                            if addr_alignment == 0 || mem.offset % addr_alignment != 0 {
+                                Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR)?
+                            }
+                        }
+
+                        let bytes = desc
+                            .image_row_pitch
+                            .checked_mul(desc.image_height)
+                            .ok_or(CL_INVALID_IMAGE_SIZE)?;
+                        if bytes > mem.size {
                             Err(err)?
                         }
                     }
@@ -689,15 +943,30 @@ pub fn create_image(
         .find(|f| *f & filtered_flags == filtered_flags)
         .ok_or(CL_IMAGE_FORMAT_NOT_SUPPORTED)?;
 
+    // resolved once here instead of on every `Context::create_texture*` call.
+    let pipe_format = format.to_pipe_format().ok_or(CL_IMAGE_FORMAT_NOT_SUPPORTED)?;
+
+    // `validate_buffer` above already confirmed that, when present, mem_object is either a buffer
+    // (CL_MEM_OBJECT_IMAGE1D_BUFFER, or a 2D image via cl_khr_image2d_from_buffer) or a 2D image
+    // (image-from-image view); either way it's the `source` whose data store this image shares.
+    let mem_object = unsafe { desc.anon_1.mem_object };
+    let source = if !mem_object.is_null() {
+        Some(mem_object.get_arc()?)
+    } else {
+        None
+    };
+
     Ok(cl_mem::from_arc(Mem::new_image(
         &c,
         desc.image_type,
         flags,
         format,
+        pipe_format,
         desc,
         elem_size,
         host_ptr,
-    )))
+        source,
+    )?))
 }
 
 pub fn get_supported_image_formats(
@@ -723,15 +992,35 @@ pub fn get_supported_image_formats(
         return Err(CL_INVALID_VALUE);
     }
 
+    // A format is only "supported" here if every image-capable device in the context can back it
+    // with the requested access mode for this image type, not just some of them -- so walk the
+    // first such device's capability table and confirm each candidate against the rest, rather
+    // than unioning (and potentially duplicating) whatever each device happens to support on its
+    // own. Devices without image support at all (e.g. a CPU device sharing a context with a GPU)
+    // have no capability table worth intersecting against and are skipped entirely, same as
+    // `create_image`'s own device-support checks do.
     let mut res = Vec::<cl_image_format>::new();
     let filtered_flags = filter_image_access_flags(flags);
-    for dev in &c.devs {
-        for f in &dev.formats {
-            let s = f.1.get(&image_type).unwrap_or(&0);
+    let image_devs: Vec<_> = c.devs.iter().filter(|d| d.image_supported()).collect();
+    if let Some((first, rest)) = image_devs.split_first() {
+        'formats: for (format, caps) in &first.formats {
+            let s = caps.get(&image_type).unwrap_or(&0);
+            if filtered_flags & s != filtered_flags {
+                continue;
+            }
 
-            if filtered_flags & s == filtered_flags {
-                res.push(*f.0);
+            for dev in rest {
+                let s = dev
+                    .formats
+                    .get(format)
+                    .and_then(|caps| caps.get(&image_type))
+                    .unwrap_or(&0);
+                if filtered_flags & s != filtered_flags {
+                    continue 'formats;
+                }
             }
+
+            res.push(*format);
         }
     }
 
@@ -831,6 +1120,11 @@ pub fn enqueue_write_buffer(
         Err(CL_INVALID_OPERATION)?
     }
 
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when
+    // the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for
+    // device associated with queue.
+    check_sub_buffer_alignment(&b, &q.device)?;
+
     let e = Event::new(
         &q,
         CL_COMMAND_WRITE_BUFFER,
@@ -843,9 +1137,6 @@ pub fn enqueue_write_buffer(
         q.flush(true)?;
     }
     Ok(())
-
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
 }
 
 pub fn enqueue_read_buffer_rect(
@@ -915,10 +1206,12 @@ pub fn enqueue_read_buffer_rect(
         host_row_pitch = r[0];
     }
 
-    // CL_INVALID_VALUE if buffer_slice_pitch is not 0 and is less than region[1] × buffer_row_pitch and not a multiple of buffer_row_pitch.
-    if buffer_slice_pitch != 0 && buffer_slice_pitch < r[1] * buffer_row_pitch && buffer_slice_pitch % buffer_row_pitch != 0 ||
-      // CL_INVALID_VALUE if host_slice_pitch is not 0 and is less than region[1] × host_row_pitch and not a multiple of host_row_pitch.
-      host_slice_pitch != 0 && host_slice_pitch < r[1] * host_row_pitch && host_slice_pitch % host_row_pitch != 0
+    // CL_INVALID_VALUE if buffer_slice_pitch is not 0 and is less than region[1] × buffer_row_pitch or not a multiple of buffer_row_pitch.
+    if buffer_slice_pitch != 0 && buffer_slice_pitch < r[1] * buffer_row_pitch ||
+      buffer_slice_pitch != 0 && buffer_slice_pitch % buffer_row_pitch != 0 ||
+      // CL_INVALID_VALUE if host_slice_pitch is not 0 and is less than region[1] × host_row_pitch or not a multiple of host_row_pitch.
+      host_slice_pitch != 0 && host_slice_pitch < r[1] * host_row_pitch ||
+      host_slice_pitch != 0 && host_slice_pitch % host_row_pitch != 0
     {
         Err(CL_INVALID_VALUE)?
     }
@@ -946,6 +1239,11 @@ pub fn enqueue_read_buffer_rect(
         Err(CL_INVALID_CONTEXT)?
     }
 
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when
+    // the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for
+    // device associated with queue.
+    check_sub_buffer_alignment(&buf, &q.device)?;
+
     let e = Event::new(
         &q,
         CL_COMMAND_READ_BUFFER_RECT,
@@ -970,9 +1268,6 @@ pub fn enqueue_read_buffer_rect(
         q.flush(true)?;
     }
     Ok(())
-
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
 }
 
 pub fn enqueue_write_buffer_rect(
@@ -1042,10 +1337,12 @@ pub fn enqueue_write_buffer_rect(
         host_row_pitch = r[0];
     }
 
-    // CL_INVALID_VALUE if buffer_slice_pitch is not 0 and is less than region[1] × buffer_row_pitch and not a multiple of buffer_row_pitch.
-    if buffer_slice_pitch != 0 && buffer_slice_pitch < r[1] * buffer_row_pitch && buffer_slice_pitch % buffer_row_pitch != 0 ||
-      // CL_INVALID_VALUE if host_slice_pitch is not 0 and is less than region[1] × host_row_pitch and not a multiple of host_row_pitch.
-      host_slice_pitch != 0 && host_slice_pitch < r[1] * host_row_pitch && host_slice_pitch % host_row_pitch != 0
+    // CL_INVALID_VALUE if buffer_slice_pitch is not 0 and is less than region[1] × buffer_row_pitch or not a multiple of buffer_row_pitch.
+    if buffer_slice_pitch != 0 && buffer_slice_pitch < r[1] * buffer_row_pitch ||
+      buffer_slice_pitch != 0 && buffer_slice_pitch % buffer_row_pitch != 0 ||
+      // CL_INVALID_VALUE if host_slice_pitch is not 0 and is less than region[1] × host_row_pitch or not a multiple of host_row_pitch.
+      host_slice_pitch != 0 && host_slice_pitch < r[1] * host_row_pitch ||
+      host_slice_pitch != 0 && host_slice_pitch % host_row_pitch != 0
     {
         Err(CL_INVALID_VALUE)?
     }
@@ -1073,6 +1370,11 @@ pub fn enqueue_write_buffer_rect(
         Err(CL_INVALID_CONTEXT)?
     }
 
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when
+    // the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for
+    // device associated with queue.
+    check_sub_buffer_alignment(&buf, &q.device)?;
+
     let e = Event::new(
         &q,
         CL_COMMAND_WRITE_BUFFER_RECT,
@@ -1097,9 +1399,6 @@ pub fn enqueue_write_buffer_rect(
         q.flush(true)?;
     }
     Ok(())
-
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
 }
 
 pub fn enqueue_copy_buffer_rect(
@@ -1205,6 +1504,12 @@ pub fn enqueue_copy_buffer_rect(
             src_slice_pitch,
         )
     {
+        // Overlapping in-place copies are the kind of thing an app can trip over without
+        // realizing it (e.g. after pitch/offset arithmetic goes subtly wrong), so surface it
+        // through pfn_notify in addition to the CL_MEM_COPY_OVERLAP return code.
+        if let Ok(errinfo) = CString::new("clEnqueueCopyBufferRect: source and destination regions overlap") {
+            q.context.notify(&errinfo, &[]);
+        }
         Err(CL_MEM_COPY_OVERLAP)?
     }
 
@@ -1218,6 +1523,12 @@ pub fn enqueue_copy_buffer_rect(
         Err(CL_INVALID_CONTEXT)?
     }
 
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if src_buffer or dst_buffer is a sub-buffer object and
+    // offset specified when the sub-buffer object is created is not aligned to
+    // CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
+    check_sub_buffer_alignment(&src, &q.device)?;
+    check_sub_buffer_alignment(&dst, &q.device)?;
+
     let e = Event::new(
         &q,
         CL_COMMAND_COPY_BUFFER_RECT,
@@ -1239,9 +1550,218 @@ pub fn enqueue_copy_buffer_rect(
     event.write_checked(cl_event::from_arc(e.clone()));
     q.queue(&e);
     Ok(())
+}
 
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if src_buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
+pub fn enqueue_copy_image_to_buffer(
+    command_queue: cl_command_queue,
+    src_image: cl_mem,
+    dst_buffer: cl_mem,
+    src_origin: *const usize,
+    region: *const usize,
+    dst_offset: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = command_queue.get_arc()?;
+    let src = src_image.get_arc()?;
+    let dst = dst_buffer.get_arc()?;
+    let evs = event_list_from_cl(num_events_in_wait_list, event_wait_list)?;
+
+    // CL_INVALID_MEM_OBJECT if src_image is not a valid image object or dst_buffer is not a valid
+    // buffer object.
+    if src.is_buffer() || !dst.is_buffer() {
+        Err(CL_INVALID_MEM_OBJECT)?
+    }
+
+    // CL_INVALID_VALUE if src_origin or region is NULL.
+    if src_origin.is_null() || region.is_null() {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    let src_ori = CLVec::from_raw_parts(src_origin);
+    let r = CLVec::from_raw_parts(region);
+
+    // CL_INVALID_VALUE if the region being read specified by src_origin and region is out of
+    // bounds for src_image.
+    src.check_bounds(&src_ori, &r)?;
+
+    let elem_size = src.image_elem_size as usize;
+    let bytes = r[0]
+        .checked_mul(r[1])
+        .and_then(|n| n.checked_mul(r[2]))
+        .and_then(|n| n.checked_mul(elem_size))
+        .ok_or(CL_INVALID_VALUE)?;
+
+    // CL_INVALID_VALUE if the region being written specified by dst_offset and the byte count
+    // derived from region (region[0] × region[1] × region[2] × size of element in bytes) is out
+    // of bounds for dst_buffer.
+    if dst_offset.checked_add(bytes).ok_or(CL_INVALID_VALUE)? > dst.size {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    // CL_INVALID_CONTEXT if the context associated with command_queue, src_image and dst_buffer
+    // are not the same or if the context associated with command_queue and events in
+    // event_wait_list are not the same.
+    if src.context != q.context
+        || dst.context != q.context
+        || evs.iter().find(|e| e.context != q.context).is_some()
+    {
+        Err(CL_INVALID_CONTEXT)?
+    }
+
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if dst_buffer is a sub-buffer object and offset specified
+    // when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value
+    // for device associated with queue.
+    check_sub_buffer_alignment(&dst, &q.device)?;
+
+    let e = Event::new(
+        &q,
+        CL_COMMAND_COPY_IMAGE_TO_BUFFER,
+        evs,
+        Box::new(move |q| src.copy_image_to_buffer(&dst, q, &src_ori, dst_offset, &r)),
+    );
+    event.write_checked(cl_event::from_arc(e.clone()));
+    q.queue(&e);
+    Ok(())
+}
+
+pub fn enqueue_copy_buffer_to_image(
+    command_queue: cl_command_queue,
+    src_buffer: cl_mem,
+    dst_image: cl_mem,
+    src_offset: usize,
+    dst_origin: *const usize,
+    region: *const usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = command_queue.get_arc()?;
+    let src = src_buffer.get_arc()?;
+    let dst = dst_image.get_arc()?;
+    let evs = event_list_from_cl(num_events_in_wait_list, event_wait_list)?;
+
+    // CL_INVALID_MEM_OBJECT if src_buffer is not a valid buffer object or dst_image is not a valid
+    // image object.
+    if !src.is_buffer() || dst.is_buffer() {
+        Err(CL_INVALID_MEM_OBJECT)?
+    }
+
+    // CL_INVALID_VALUE if dst_origin or region is NULL.
+    if dst_origin.is_null() || region.is_null() {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    let dst_ori = CLVec::from_raw_parts(dst_origin);
+    let r = CLVec::from_raw_parts(region);
+
+    // CL_INVALID_VALUE if the region being written specified by dst_origin and region is out of
+    // bounds for dst_image.
+    dst.check_bounds(&dst_ori, &r)?;
+
+    let elem_size = dst.image_elem_size as usize;
+    let bytes = r[0]
+        .checked_mul(r[1])
+        .and_then(|n| n.checked_mul(r[2]))
+        .and_then(|n| n.checked_mul(elem_size))
+        .ok_or(CL_INVALID_VALUE)?;
+
+    // CL_INVALID_VALUE if the region being read specified by src_offset and the byte count derived
+    // from region (region[0] × region[1] × region[2] × size of element in bytes) is out of bounds
+    // for src_buffer.
+    if src_offset.checked_add(bytes).ok_or(CL_INVALID_VALUE)? > src.size {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    // CL_INVALID_CONTEXT if the context associated with command_queue, src_buffer and dst_image
+    // are not the same or if the context associated with command_queue and events in
+    // event_wait_list are not the same.
+    if src.context != q.context
+        || dst.context != q.context
+        || evs.iter().find(|e| e.context != q.context).is_some()
+    {
+        Err(CL_INVALID_CONTEXT)?
+    }
+
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if src_buffer is a sub-buffer object and offset specified
+    // when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value
+    // for device associated with queue.
+    check_sub_buffer_alignment(&src, &q.device)?;
+
+    let e = Event::new(
+        &q,
+        CL_COMMAND_COPY_BUFFER_TO_IMAGE,
+        evs,
+        Box::new(move |q| src.copy_buffer_to_image(&dst, q, src_offset, &dst_ori, &r)),
+    );
+    event.write_checked(cl_event::from_arc(e.clone()));
+    q.queue(&e);
+    Ok(())
+}
+
+pub fn enqueue_fill_buffer(
+    command_queue: cl_command_queue,
+    buffer: cl_mem,
+    pattern: *const ::std::os::raw::c_void,
+    pattern_size: usize,
+    offset: usize,
+    size: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = command_queue.get_arc()?;
+    let b = buffer.get_arc()?;
+
+    // CL_INVALID_VALUE if pattern is NULL or if pattern_size is 0, or is not one of 1, 2, 4, 8, 16,
+    // 32, 64 or 128. Checked before any of the region math below so a bogus pattern can never reach
+    // the size == 0 special case.
+    if pattern.is_null() || !matches!(pattern_size, 1 | 2 | 4 | 8 | 16 | 32 | 64 | 128) {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    // CL_INVALID_VALUE if offset or offset + size require accessing elements outside the buffer
+    // object, or if offset and size are not a multiple of pattern_size.
+    if offset % pattern_size != 0
+        || size % pattern_size != 0
+        || offset.checked_add(size).ok_or(CL_INVALID_VALUE)? > b.size
+    {
+        Err(CL_INVALID_VALUE)?
+    }
+
+    let evs = event_list_from_cl(num_events_in_wait_list, event_wait_list)?;
+
+    // CL_INVALID_CONTEXT if the context associated with command_queue and buffer are not the same
+    // or if the context associated with command_queue and events in event_wait_list are not the
+    // same.
+    if b.context != q.context || evs.iter().find(|e| e.context != q.context).is_some() {
+        Err(CL_INVALID_CONTEXT)?
+    }
+
+    check_sub_buffer_alignment(&b, &q.device)?;
+
+    // Per the spec, `pattern`'s memory can be reused by the application as soon as this call
+    // returns, so it has to be copied now rather than captured as a raw pointer into the event's
+    // work closure.
+    let pattern = unsafe { slice::from_raw_parts(pattern.cast::<u8>(), pattern_size) }.to_vec();
+
+    let e = Event::new(
+        &q,
+        CL_COMMAND_FILL_BUFFER,
+        evs,
+        Box::new(move |q| {
+            // A zero-size fill is a no-op; it still resolves to a valid completed event instead of
+            // handing a zero-length region down to the driver.
+            if size == 0 {
+                return Ok(());
+            }
+            b.fill(q, pattern.as_ptr().cast(), pattern.len(), offset, size)
+        }),
+    );
+    event.write_checked(cl_event::from_arc(e.clone()));
+    q.queue(&e);
+    Ok(())
 }
 
 pub fn enqueue_map_buffer(
@@ -1303,20 +1823,40 @@ pub fn enqueue_map_buffer(
         Err(CL_INVALID_CONTEXT)?
     }
 
-    if !block || num_events_in_wait_list > 0 || !event.is_null() {
-        println!("enqueue_map_buffer not implemented");
-        Err(CL_MAP_FAILURE)?
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when
+    // the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for
+    // the device associated with queue.
+    check_sub_buffer_alignment(&b, &q.device)?;
+
+    let writable = bit_check(map_flags, CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION);
+
+    // CL_INVALID_OPERATION if mapping would lead to overlapping regions being mapped for writing.
+    if writable && b.has_writable_overlap(offset, size, writable) {
+        Err(CL_INVALID_OPERATION)?
+    }
+
+    // The pointer has to be handed back to the caller immediately, so the actual mapping can't be
+    // deferred into the event's work closure the way e.g. `enqueue_write_buffer` defers its
+    // transfer. Instead we resolve it synchronously here -- same as the previous blocking-only
+    // code did -- and only use the event to track completion/ordering against `evs` and whatever
+    // gets queued after it.
+    if block {
+        for e in &evs {
+            e.wait();
+        }
     }
+    let ptr = b.map(&q, offset, size, writable);
 
+    let e = Event::new(&q, CL_COMMAND_MAP_BUFFER, evs, Box::new(|_| Ok(())));
+    event.write_checked(cl_event::from_arc(e.clone()));
+    q.queue(&e);
     if block {
         q.flush(true)?;
     }
 
-    Ok(b.map(&q, offset, size))
+    Ok(ptr)
     // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for the device associated with queue. This error code is missing before version 1.1.
     // CL_MAP_FAILURE if there is a failure to map the requested region into the host address space. This error cannot occur for buffer objects created with CL_MEM_USE_HOST_PTR or CL_MEM_ALLOC_HOST_PTR.
-    // CL_INVALID_OPERATION if mapping would lead to overlapping regions being mapped for writing.
 }
 
 pub fn enqueue_unmap_mem_object(
@@ -1328,7 +1868,7 @@ pub fn enqueue_unmap_mem_object(
     event: *mut cl_event,
 ) -> CLResult<()> {
     let q = command_queue.get_arc()?;
-    let m = memobj.get_ref()?;
+    let m = memobj.get_arc()?;
     let evs = event_list_from_cl(num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_CONTEXT if context associated with command_queue and memobj are not the same or if
@@ -1337,16 +1877,25 @@ pub fn enqueue_unmap_mem_object(
         Err(CL_INVALID_CONTEXT)?
     }
 
-    // CL_INVALID_VALUE if mapped_ptr is not a valid pointer returned by clEnqueueMapBuffer or
-    // clEnqueueMapImage for memobj.
-    if !m.unmap(mapped_ptr) {
-        Err(CL_INVALID_VALUE)?
-    }
-
-    if num_events_in_wait_list > 0 || !event.is_null() {
-        println!("enqueue_unmap_mem_object not implemented");
-        Err(CL_OUT_OF_HOST_MEMORY)?
-    }
+    // Unlike the map side there's nothing that has to be handed back to the caller synchronously,
+    // so the actual unmap -- including any shadow-buffer copy-back -- can be deferred into the
+    // event's work closure like any other enqueued command, and naturally wait on `evs` first.
+    let e = Event::new(
+        &q,
+        CL_COMMAND_UNMAP_MEM_OBJECT,
+        evs,
+        Box::new(move |_| {
+            // CL_INVALID_VALUE if mapped_ptr is not a valid pointer returned by
+            // clEnqueueMapBuffer or clEnqueueMapImage for memobj.
+            if m.unmap(mapped_ptr) {
+                Ok(())
+            } else {
+                Err(CL_INVALID_VALUE)
+            }
+        }),
+    );
+    event.write_checked(cl_event::from_arc(e.clone()));
+    q.queue(&e);
 
     Ok(())
 }
@@ -84,15 +84,27 @@ pub trait CLInfoObj<I, O> {
     }
 }
 
+// `write_cl` is the serialization primitive: it appends this value's wire representation to an
+// already-allocated buffer instead of handing back an owned `Vec<u8>`, so a container type (e.g.
+// `Vec<T>`) can serialize every element into one buffer instead of allocating and then
+// `append`-ing one sub-`Vec` per element. `cl_vec`/`cl_prop` stay around as the entry point
+// `get_info`/`get_info_obj` callers use; they just allocate the single top-level buffer and
+// delegate to `write_cl`.
 pub trait CLProp {
-    fn cl_vec(&self) -> Vec<u8>;
+    fn write_cl(&self, out: &mut Vec<u8>);
+
+    fn cl_vec(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_cl(&mut out);
+        out
+    }
 }
 
 macro_rules! cl_prop_for_type {
     ($ty: ty) => {
         impl CLProp for $ty {
-            fn cl_vec(&self) -> Vec<u8> {
-                self.to_ne_bytes().to_vec()
+            fn write_cl(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(&self.to_ne_bytes());
             }
         }
     };
@@ -101,11 +113,10 @@ macro_rules! cl_prop_for_type {
 macro_rules! cl_prop_for_struct {
     ($ty: ty) => {
         impl CLProp for $ty {
-            fn cl_vec(&self) -> Vec<u8> {
-                unsafe {
+            fn write_cl(&self, out: &mut Vec<u8>) {
+                out.extend_from_slice(unsafe {
                     slice::from_raw_parts((self as *const Self) as *const u8, size_of::<Self>())
-                }
-                .to_vec()
+                });
             }
         }
     };
@@ -122,31 +133,30 @@ cl_prop_for_struct!(cl_image_format);
 cl_prop_for_struct!(cl_name_version);
 
 impl CLProp for bool {
-    fn cl_vec(&self) -> Vec<u8> {
-        cl_prop::<cl_bool>(if *self { CL_TRUE } else { CL_FALSE })
+    fn write_cl(&self, out: &mut Vec<u8>) {
+        (if *self { CL_TRUE } else { CL_FALSE } as cl_bool).write_cl(out)
     }
 }
 
 impl CLProp for String {
-    fn cl_vec(&self) -> Vec<u8> {
-        let mut c = self.clone();
-        c.push('\0');
-        c.into_bytes()
+    fn write_cl(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.as_bytes());
+        out.push(0);
     }
 }
 
 impl CLProp for &str {
-    fn cl_vec(&self) -> Vec<u8> {
-        CString::new(*self)
-            .or_else(|_| CString::new(b"\0".to_vec()))
-            .unwrap()
-            .into_bytes_with_nul()
+    fn write_cl(&self, out: &mut Vec<u8>) {
+        match CString::new(*self) {
+            Ok(c) => out.extend_from_slice(c.as_bytes_with_nul()),
+            Err(_) => out.push(0),
+        }
     }
 }
 
 impl CLProp for &CStr {
-    fn cl_vec(&self) -> Vec<u8> {
-        self.to_bytes_with_nul().to_vec()
+    fn write_cl(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(self.to_bytes_with_nul());
     }
 }
 
@@ -154,12 +164,10 @@ impl<T> CLProp for Vec<T>
 where
     T: CLProp,
 {
-    fn cl_vec(&self) -> Vec<u8> {
-        let mut res: Vec<u8> = Vec::new();
+    fn write_cl(&self, out: &mut Vec<u8>) {
         for i in self {
-            res.append(&mut i.cl_vec())
+            i.write_cl(out);
         }
-        res
     }
 }
 
@@ -167,24 +175,22 @@ impl<T> CLProp for &Vec<T>
 where
     T: CLProp,
 {
-    fn cl_vec(&self) -> Vec<u8> {
-        let mut res: Vec<u8> = Vec::new();
+    fn write_cl(&self, out: &mut Vec<u8>) {
         for i in *self {
-            res.append(&mut i.cl_vec())
+            i.write_cl(out);
         }
-        res
     }
 }
 
 impl<T> CLProp for *const T {
-    fn cl_vec(&self) -> Vec<u8> {
-        (*self as usize).cl_vec()
+    fn write_cl(&self, out: &mut Vec<u8>) {
+        (*self as usize).write_cl(out)
     }
 }
 
 impl<T> CLProp for *mut T {
-    fn cl_vec(&self) -> Vec<u8> {
-        (*self as usize).cl_vec()
+    fn write_cl(&self, out: &mut Vec<u8>) {
+        (*self as usize).write_cl(out)
     }
 }
 
@@ -252,6 +258,104 @@ pub fn event_list_from_cl<'a>(
     )?)
 }
 
+// Every `cl_int` status this driver can return, paired with its spec name. Used to turn a bare
+// status code into something an application's `pfn_notify`/a debug log can actually print instead
+// of a number the developer has to go look up in cl.h.
+const CL_STATUS_NAMES: &[(cl_int, &[u8])] = &[
+    (CL_SUCCESS as cl_int, b"CL_SUCCESS\0"),
+    (CL_DEVICE_NOT_FOUND as cl_int, b"CL_DEVICE_NOT_FOUND\0"),
+    (CL_OUT_OF_HOST_MEMORY as cl_int, b"CL_OUT_OF_HOST_MEMORY\0"),
+    (CL_OUT_OF_RESOURCES as cl_int, b"CL_OUT_OF_RESOURCES\0"),
+    (CL_MEM_COPY_OVERLAP as cl_int, b"CL_MEM_COPY_OVERLAP\0"),
+    (
+        CL_IMAGE_FORMAT_NOT_SUPPORTED as cl_int,
+        b"CL_IMAGE_FORMAT_NOT_SUPPORTED\0",
+    ),
+    (
+        CL_MISALIGNED_SUB_BUFFER_OFFSET as cl_int,
+        b"CL_MISALIGNED_SUB_BUFFER_OFFSET\0",
+    ),
+    (
+        CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST as cl_int,
+        b"CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST\0",
+    ),
+    (
+        CL_PROFILING_INFO_NOT_AVAILABLE as cl_int,
+        b"CL_PROFILING_INFO_NOT_AVAILABLE\0",
+    ),
+    (CL_INVALID_VALUE, b"CL_INVALID_VALUE\0"),
+    (CL_INVALID_DEVICE_TYPE, b"CL_INVALID_DEVICE_TYPE\0"),
+    (CL_INVALID_PLATFORM, b"CL_INVALID_PLATFORM\0"),
+    (CL_INVALID_DEVICE, b"CL_INVALID_DEVICE\0"),
+    (CL_INVALID_CONTEXT, b"CL_INVALID_CONTEXT\0"),
+    (CL_INVALID_QUEUE_PROPERTIES, b"CL_INVALID_QUEUE_PROPERTIES\0"),
+    (CL_INVALID_COMMAND_QUEUE, b"CL_INVALID_COMMAND_QUEUE\0"),
+    (CL_INVALID_HOST_PTR, b"CL_INVALID_HOST_PTR\0"),
+    (CL_INVALID_MEM_OBJECT, b"CL_INVALID_MEM_OBJECT\0"),
+    (
+        CL_INVALID_IMAGE_FORMAT_DESCRIPTOR,
+        b"CL_INVALID_IMAGE_FORMAT_DESCRIPTOR\0",
+    ),
+    (CL_INVALID_IMAGE_SIZE, b"CL_INVALID_IMAGE_SIZE\0"),
+    (CL_INVALID_IMAGE_DESCRIPTOR, b"CL_INVALID_IMAGE_DESCRIPTOR\0"),
+    (CL_INVALID_SAMPLER, b"CL_INVALID_SAMPLER\0"),
+    (CL_INVALID_BUFFER_SIZE, b"CL_INVALID_BUFFER_SIZE\0"),
+    (CL_INVALID_PROPERTY, b"CL_INVALID_PROPERTY\0"),
+    (CL_INVALID_PROGRAM, b"CL_INVALID_PROGRAM\0"),
+    (
+        CL_INVALID_PROGRAM_EXECUTABLE,
+        b"CL_INVALID_PROGRAM_EXECUTABLE\0",
+    ),
+    (CL_INVALID_KERNEL_NAME, b"CL_INVALID_KERNEL_NAME\0"),
+    (
+        CL_INVALID_KERNEL_DEFINITION,
+        b"CL_INVALID_KERNEL_DEFINITION\0",
+    ),
+    (CL_INVALID_KERNEL, b"CL_INVALID_KERNEL\0"),
+    (CL_INVALID_ARG_INDEX, b"CL_INVALID_ARG_INDEX\0"),
+    (CL_INVALID_ARG_VALUE, b"CL_INVALID_ARG_VALUE\0"),
+    (CL_INVALID_ARG_SIZE, b"CL_INVALID_ARG_SIZE\0"),
+    (CL_INVALID_KERNEL_ARGS, b"CL_INVALID_KERNEL_ARGS\0"),
+    (CL_INVALID_WORK_DIMENSION, b"CL_INVALID_WORK_DIMENSION\0"),
+    (CL_INVALID_WORK_GROUP_SIZE, b"CL_INVALID_WORK_GROUP_SIZE\0"),
+    (CL_INVALID_WORK_ITEM_SIZE, b"CL_INVALID_WORK_ITEM_SIZE\0"),
+    (CL_INVALID_GLOBAL_OFFSET, b"CL_INVALID_GLOBAL_OFFSET\0"),
+    (CL_INVALID_EVENT_WAIT_LIST, b"CL_INVALID_EVENT_WAIT_LIST\0"),
+    (CL_INVALID_EVENT, b"CL_INVALID_EVENT\0"),
+    (CL_INVALID_OPERATION, b"CL_INVALID_OPERATION\0"),
+    (CL_INVALID_GLOBAL_WORK_SIZE, b"CL_INVALID_GLOBAL_WORK_SIZE\0"),
+    (CL_INVALID_DEVICE_QUEUE, b"CL_INVALID_DEVICE_QUEUE\0"),
+];
+
+const CL_UNKNOWN_STATUS: &[u8] = b"unknown CL status\0";
+
+/// `true` for any status that isn't `CL_SUCCESS`/`CL_COMPLETE` (both are `0`) -- i.e. a real
+/// failure rather than a success or purely informational return.
+pub fn is_cl_error(status: cl_int) -> bool {
+    status < CL_SUCCESS as cl_int
+}
+
+/// Looks up `status`'s name from the `cl.h` enums (e.g. `CL_INVALID_VALUE`), falling back to a
+/// generic "unknown CL status" description for anything this table doesn't recognize.
+pub fn cl_error_name(status: cl_int) -> &'static CStr {
+    let bytes = CL_STATUS_NAMES
+        .iter()
+        .find(|(code, _)| *code == status)
+        .map_or(CL_UNKNOWN_STATUS, |(_, name)| name);
+    CStr::from_bytes_with_nul(bytes).unwrap()
+}
+
+/// Logs `errinfo` (the message that goes into `pfn_notify`'s `errinfo`) to stderr when
+/// `RUSTICL_DEBUG=errors` is set, so a failing app can be diagnosed without a debugger attached.
+pub fn log_cl_error(errinfo: &CStr) {
+    let verbose = std::env::var("RUSTICL_DEBUG")
+        .map(|v| v.split(',').any(|opt| opt == "errors"))
+        .unwrap_or(false);
+    if verbose {
+        eprintln!("rusticl: {}", errinfo.to_string_lossy());
+    }
+}
+
 pub fn check_cb<T>(cb: &Option<T>, user_data: *mut c_void) -> Result<(), cl_int> {
     // CL_INVALID_VALUE if pfn_notify is NULL but user_data is not NULL.
     if cb.is_none() && !user_data.is_null() {
@@ -1,28 +1,67 @@
 extern crate mesa_rust;
+extern crate mesa_rust_gen;
 extern crate mesa_rust_util;
 extern crate rusticl_opencl_gen;
 
 use crate::api::icd::*;
+use crate::api::types::*;
+use crate::api::util::log_cl_error;
 use crate::core::device::*;
 use crate::core::format::*;
+use crate::core::memory::{create_pipe_box, sw_copy};
 use crate::core::util::*;
 use crate::impl_cl_type_trait;
 
+use self::mesa_rust::pipe::context::RWFlags;
 use self::mesa_rust::pipe::resource::*;
+use self::mesa_rust::pipe::transfer::*;
+use self::mesa_rust_gen::*;
 use self::mesa_rust_util::properties::Properties;
 use self::rusticl_opencl_gen::*;
 
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::ffi::CStr;
 use std::os::raw::c_void;
 use std::sync::Arc;
 use std::sync::Mutex;
 
+// Wraps `pfn_notify`/`user_data` from `clCreateContext`/`clCreateContextFromType` so
+// `Context::notify` can be called from any worker thread (e.g. queue execution), not just the
+// thread that created the context. This is sound because `user_data` is an
+// opaque token the application owns for the context's entire lifetime; making sure whatever it
+// points to is safe to touch from another thread is the caller's responsibility, exactly as it
+// would be for a native OpenCL implementation invoking this callback from its own driver thread.
+struct Notify {
+    cb: CreateContextCB,
+    user_data: usize,
+}
+
+unsafe impl Send for Notify {}
+unsafe impl Sync for Notify {}
+
+// cl_khr_gl_sharing: the application's GL context and whichever platform-specific display/
+// share-group handle accompanies it (`CL_EGL_DISPLAY_KHR`/`CL_GLX_DISPLAY_KHR`/`CL_WGL_HDC_KHR`/
+// `CL_CGL_SHAREGROUP_KHR`), recorded as opaque tokens at `clCreateContext` time. rusticl doesn't
+// interpret either handle itself -- this is just the groundwork `clCreateFromGLBuffer`/
+// `clCreateFromGLTexture` will need to resolve a shared object back to this context.
+#[derive(Clone, Copy)]
+pub struct GlInterop {
+    pub gl_context: usize,
+    pub display: usize,
+}
+
 pub struct Context {
     pub base: CLObjectBase<CL_INVALID_CONTEXT>,
     pub devs: Vec<Arc<Device>>,
     pub properties: Properties<cl_context_properties>,
     pub dtors: Mutex<Vec<Box<dyn Fn(cl_context) -> ()>>>,
+    // coarse-grained SVM: base pointer -> (size, alignment) of a `clSVMAlloc`ed range, so a raw
+    // pointer handed to e.g. `clCreateBuffer` or a kernel arg can be recognized as SVM-backed
+    // without the caller threading the allocation's extent through separately.
+    svm_ptrs: Mutex<HashMap<usize, (usize, usize)>>,
+    notify: Option<Notify>,
+    pub gl_interop: Option<GlInterop>,
 }
 
 impl_cl_type_trait!(cl_context, Context, CL_INVALID_CONTEXT);
@@ -31,39 +70,97 @@ impl Context {
     pub fn new(
         devs: Vec<Arc<Device>>,
         properties: Properties<cl_context_properties>,
+        pfn_notify: Option<CreateContextCB>,
+        user_data: *mut c_void,
+        gl_interop: Option<GlInterop>,
     ) -> Arc<Context> {
         Arc::new(Self {
             base: CLObjectBase::new(),
             devs: devs,
             properties: properties,
             dtors: Mutex::new(Vec::new()),
+            svm_ptrs: Mutex::new(HashMap::new()),
+            notify: pfn_notify.map(|cb| Notify {
+                cb: cb,
+                user_data: user_data as usize,
+            }),
+            gl_interop: gl_interop,
         })
     }
 
-    pub fn create_buffer(&self, size: usize) -> CLResult<HashMap<Arc<Device>, Arc<PipeResource>>> {
+    // Surfaces a runtime error to the application's `pfn_notify`, per the clCreateContext spec.
+    // It is called for a command that fails asynchronously on the worker thread (`Event::call`)
+    // and for an overlapping copy caught by `check_copy_overlap` in clEnqueueCopyBufferRect;
+    // allocation failures are only returned as error codes and are not reported here. Also logged
+    // to stderr under RUSTICL_DEBUG=errors so the same failure is diagnosable without an app that
+    // actually registered a callback.
+    pub fn notify(&self, errinfo: &CStr, private_info: &[u8]) {
+        log_cl_error(errinfo);
+
+        if let Some(n) = &self.notify {
+            unsafe {
+                (n.cb)(
+                    errinfo.as_ptr(),
+                    private_info.as_ptr().cast(),
+                    private_info.len(),
+                    n.user_data as *mut c_void,
+                );
+            }
+        }
+    }
+
+    pub fn add_svm_ptr(&self, ptr: usize, size: usize, alignment: usize) {
+        self.svm_ptrs.lock().unwrap().insert(ptr, (size, alignment));
+    }
+
+    pub fn remove_svm_ptr(&self, ptr: usize) -> Option<(usize, usize)> {
+        self.svm_ptrs.lock().unwrap().remove(&ptr)
+    }
+
+    /// Finds the base address of the SVM allocation (if any) whose range contains `ptr`, e.g. for
+    /// `CL_MEM_USES_SVM_POINTER` or resolving a kernel arg that points into the middle of one.
+    pub fn find_svm_alloc(&self, ptr: usize) -> Option<usize> {
+        self.svm_ptrs
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(&base, &(size, _))| ptr >= base && ptr < base + size)
+            .map(|(&base, _)| base)
+    }
+
+    pub fn create_buffer(
+        &self,
+        size: usize,
+        res_type: ResourceType,
+    ) -> CLResult<HashMap<Arc<Device>, Arc<PipeResource>>> {
         let adj_size: u32 = size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
         let mut res = HashMap::new();
         for dev in &self.devs {
             let resource = dev
                 .screen()
-                .resource_create_buffer(adj_size)
+                .resource_create_buffer(adj_size, res_type)
                 .ok_or(CL_OUT_OF_RESOURCES);
             res.insert(Arc::clone(dev), Arc::new(resource?));
         }
         Ok(res)
     }
 
+    // CL_MEM_USE_HOST_PTR: try to wrap `user_ptr` directly on each device, and only fall back to a
+    // normal, separate allocation (leaving the later `CL_MEM_COPY_HOST_PTR`-style upload to the
+    // caller) on devices where the driver can't import host memory for this resource.
     pub fn create_buffer_from_user(
         &self,
         size: usize,
         user_ptr: *mut c_void,
+        res_type: ResourceType,
     ) -> CLResult<HashMap<Arc<Device>, Arc<PipeResource>>> {
         let adj_size: u32 = size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
         let mut res = HashMap::new();
         for dev in &self.devs {
-            let resource = dev
-                .screen()
+            let screen = dev.screen();
+            let resource = screen
                 .resource_create_buffer_from_user(adj_size, user_ptr)
+                .or_else(|| screen.resource_create_buffer(adj_size, res_type))
                 .ok_or(CL_OUT_OF_RESOURCES);
             res.insert(Arc::clone(dev), Arc::new(resource?));
         }
@@ -73,7 +170,8 @@ impl Context {
     pub fn create_texture(
         &self,
         desc: &cl_image_desc,
-        format: &cl_image_format,
+        format: pipe_format,
+        res_type: ResourceType,
     ) -> CLResult<HashMap<Arc<Device>, Arc<PipeResource>>> {
         let width = desc
             .image_width
@@ -92,13 +190,12 @@ impl Context {
             .try_into()
             .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
         let target = cl_mem_type_to_texture_target(desc.image_type);
-        let format = format.to_pipe_format().unwrap();
 
         let mut res = HashMap::new();
         for dev in &self.devs {
             let resource = dev
                 .screen()
-                .resource_create_texture(width, height, depth, array_size, target, format)
+                .resource_create_texture(width, height, depth, array_size, target, format, res_type)
                 .ok_or(CL_OUT_OF_RESOURCES);
             res.insert(Arc::clone(dev), Arc::new(resource?));
         }
@@ -108,8 +205,9 @@ impl Context {
     pub fn create_texture_from_user(
         &self,
         desc: &cl_image_desc,
-        format: &cl_image_format,
+        format: pipe_format,
         user_ptr: *mut c_void,
+        res_type: ResourceType,
     ) -> CLResult<HashMap<Arc<Device>, Arc<PipeResource>>> {
         let width = desc
             .image_width
@@ -128,20 +226,300 @@ impl Context {
             .try_into()
             .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
         let target = cl_mem_type_to_texture_target(desc.image_type);
-        let format = format.to_pipe_format().unwrap();
 
         let mut res = HashMap::new();
         for dev in &self.devs {
-            let resource = dev
-                .screen()
+            let screen = dev.screen();
+            let resource = screen
                 .resource_create_texture_from_user(
                     width, height, depth, array_size, target, format, user_ptr,
                 )
+                .or_else(|| {
+                    screen.resource_create_texture(
+                        width, height, depth, array_size, target, format, res_type,
+                    )
+                })
+                .ok_or(CL_OUT_OF_RESOURCES);
+            res.insert(Arc::clone(dev), Arc::new(resource?));
+        }
+        Ok(res)
+    }
+
+    // cl_khr_image2d_from_buffer: `buffer` keeps its own data store, and this just asks each
+    // device's screen for a 2D texture resource that aliases it, so writes through either the
+    // buffer or the image are visible to the other without a copy.
+    pub fn create_texture_from_buffer(
+        &self,
+        desc: &cl_image_desc,
+        format: pipe_format,
+        buffer: &HashMap<Arc<Device>, Arc<PipeResource>>,
+    ) -> CLResult<HashMap<Arc<Device>, Arc<PipeResource>>> {
+        let width = desc
+            .image_width
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let height = desc
+            .image_height
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let row_pitch = desc
+            .image_row_pitch
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+
+        let mut res = HashMap::new();
+        for dev in &self.devs {
+            let buf_res = buffer.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+            let resource = dev
+                .screen()
+                .resource_create_texture_from_buffer(width, height, row_pitch, format, buf_res)
                 .ok_or(CL_OUT_OF_RESOURCES);
             res.insert(Arc::clone(dev), Arc::new(resource?));
         }
         Ok(res)
     }
+
+    // Per device, maps `res` over `b` and walks it with `copy`. `is_buffer` handles
+    // `CL_MEM_OBJECT_IMAGE1D_BUFFER`, where the "image" is physically a buffer resource and has to
+    // go through `buffer_map` instead of `texture_map`; `b.x`/`b.width` are still meaningful in
+    // that case since `create_pipe_box` collapses a buffer's box down to just those two fields.
+    fn for_each_mapped<F: Fn(&PipeTransfer)>(
+        &self,
+        res: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        is_buffer: bool,
+        b: &pipe_box,
+        rw: RWFlags,
+        copy: F,
+    ) -> CLResult<()> {
+        for dev in &self.devs {
+            let resource = res.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+            let pipe = dev.screen().create_context().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+            let tx = if is_buffer {
+                pipe.buffer_map(resource, b.x, b.width, rw, true)
+            } else {
+                pipe.texture_map(resource, b, rw, true)
+            };
+
+            copy(&tx);
+        }
+        Ok(())
+    }
+
+    /// Uploads `src` into every device's copy of `res`, honoring the row/slice pitch the driver's
+    /// `texture_map`/`buffer_map` actually hands back instead of assuming the resource is tightly
+    /// packed.
+    pub fn write_image(
+        &self,
+        res: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        mem_type: cl_mem_object_type,
+        image_desc: &cl_image_desc,
+        origin: CLVec<usize>,
+        region: CLVec<usize>,
+        src: *const c_void,
+        src_row_pitch: usize,
+        src_slice_pitch: usize,
+    ) -> CLResult<()> {
+        let is_buffer = mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER;
+        let b = create_pipe_box(origin, region, mem_type, image_desc, 0);
+
+        self.for_each_mapped(res, is_buffer, &b, RWFlags::W, |tx| {
+            sw_copy(
+                src,
+                tx.ptr(),
+                &region,
+                &CLVec::default(),
+                src_row_pitch,
+                src_slice_pitch,
+                &CLVec::default(),
+                tx.stride() as usize,
+                tx.layer_stride() as usize,
+            );
+        })
+    }
+
+    /// Downloads `region` of `res` (on the first device, which is what every caller needs: a
+    /// host-visible snapshot of one device's data) into `dst`, honoring the driver's actual
+    /// row/slice pitch rather than `image_desc`'s declared one.
+    pub fn read_image(
+        &self,
+        res: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        mem_type: cl_mem_object_type,
+        image_desc: &cl_image_desc,
+        origin: CLVec<usize>,
+        region: CLVec<usize>,
+        dst: *mut c_void,
+        dst_row_pitch: usize,
+        dst_slice_pitch: usize,
+    ) -> CLResult<()> {
+        let is_buffer = mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER;
+        let b = create_pipe_box(origin, region, mem_type, image_desc, 0);
+        let dev = self.devs.first().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let resource = res.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let pipe = dev.screen().create_context().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let tx = if is_buffer {
+            pipe.buffer_map(resource, b.x, b.width, RWFlags::R, true)
+        } else {
+            pipe.texture_map(resource, &b, RWFlags::R, true)
+        };
+
+        sw_copy(
+            tx.ptr(),
+            dst,
+            &region,
+            &CLVec::default(),
+            tx.stride() as usize,
+            tx.layer_stride() as usize,
+            &CLVec::default(),
+            dst_row_pitch,
+            dst_slice_pitch,
+        );
+
+        Ok(())
+    }
+
+    /// Copies `region` texels of `src` (origin `src_origin`) into `dst` (origin `dst_origin`) on
+    /// the first device shared by both resources, honoring each side's actual mapped pitch. Either
+    /// side may be an `IMAGE1D_BUFFER` backed by a plain buffer resource.
+    pub fn copy_image(
+        &self,
+        src: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        src_mem_type: cl_mem_object_type,
+        src_image_desc: &cl_image_desc,
+        src_origin: CLVec<usize>,
+        dst: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        dst_mem_type: cl_mem_object_type,
+        dst_image_desc: &cl_image_desc,
+        dst_origin: CLVec<usize>,
+        region: CLVec<usize>,
+    ) -> CLResult<()> {
+        let dev = self.devs.first().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let pipe = dev.screen().create_context().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+
+        let src_is_buffer = src_mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER;
+        let src_res = src.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let src_b = create_pipe_box(src_origin, region, src_mem_type, src_image_desc, 0);
+        let tx_src = if src_is_buffer {
+            pipe.buffer_map(src_res, src_b.x, src_b.width, RWFlags::R, true)
+        } else {
+            pipe.texture_map(src_res, &src_b, RWFlags::R, true)
+        };
+
+        let dst_is_buffer = dst_mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER;
+        let dst_res = dst.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let dst_b = create_pipe_box(dst_origin, region, dst_mem_type, dst_image_desc, 0);
+        let tx_dst = if dst_is_buffer {
+            pipe.buffer_map(dst_res, dst_b.x, dst_b.width, RWFlags::W, true)
+        } else {
+            pipe.texture_map(dst_res, &dst_b, RWFlags::W, true)
+        };
+
+        sw_copy(
+            tx_src.ptr(),
+            tx_dst.ptr(),
+            &region,
+            &CLVec::default(),
+            tx_src.stride() as usize,
+            tx_src.layer_stride() as usize,
+            &CLVec::default(),
+            tx_dst.stride() as usize,
+            tx_dst.layer_stride() as usize,
+        );
+
+        Ok(())
+    }
+
+    /// Copies `region` of `src` (a plain buffer of `src_size` bytes, read from `src_offset` with
+    /// `row_pitch`/`slice_pitch`) into `dst` (an image at `dst_origin`) on the first device shared
+    /// by both resources.
+    pub fn copy_buffer_to_image(
+        &self,
+        src: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        src_size: usize,
+        src_offset: usize,
+        dst: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        dst_mem_type: cl_mem_object_type,
+        dst_image_desc: &cl_image_desc,
+        dst_origin: CLVec<usize>,
+        region: CLVec<usize>,
+        row_pitch: usize,
+        slice_pitch: usize,
+    ) -> CLResult<()> {
+        let dev = self.devs.first().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let pipe = dev.screen().create_context().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+
+        let src_res = src.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let src_size: i32 = src_size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let tx_src = pipe.buffer_map(src_res, 0, src_size, RWFlags::R, true);
+
+        let dst_is_buffer = dst_mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER;
+        let dst_res = dst.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let dst_b = create_pipe_box(dst_origin, region, dst_mem_type, dst_image_desc, 0);
+        let tx_dst = if dst_is_buffer {
+            pipe.buffer_map(dst_res, dst_b.x, dst_b.width, RWFlags::W, true)
+        } else {
+            pipe.texture_map(dst_res, &dst_b, RWFlags::W, true)
+        };
+
+        sw_copy(
+            tx_src.ptr(),
+            tx_dst.ptr(),
+            &region,
+            &CLVec::new([src_offset, 0, 0]),
+            row_pitch,
+            slice_pitch,
+            &CLVec::default(),
+            tx_dst.stride() as usize,
+            tx_dst.layer_stride() as usize,
+        );
+
+        Ok(())
+    }
+
+    /// Copies `region` of `src` (an image at `src_origin`) into `dst` (a plain buffer, offset
+    /// `dst_offset`) on the first device shared by both resources.
+    pub fn copy_image_to_buffer(
+        &self,
+        src: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        src_mem_type: cl_mem_object_type,
+        src_image_desc: &cl_image_desc,
+        src_origin: CLVec<usize>,
+        dst: &HashMap<Arc<Device>, Arc<PipeResource>>,
+        dst_size: usize,
+        dst_offset: usize,
+        region: CLVec<usize>,
+        row_pitch: usize,
+        slice_pitch: usize,
+    ) -> CLResult<()> {
+        let dev = self.devs.first().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let pipe = dev.screen().create_context().ok_or(CL_OUT_OF_HOST_MEMORY)?;
+
+        let src_is_buffer = src_mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER;
+        let src_res = src.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let src_b = create_pipe_box(src_origin, region, src_mem_type, src_image_desc, 0);
+        let tx_src = if src_is_buffer {
+            pipe.buffer_map(src_res, src_b.x, src_b.width, RWFlags::R, true)
+        } else {
+            pipe.texture_map(src_res, &src_b, RWFlags::R, true)
+        };
+
+        let dst_res = dst.get(dev).ok_or(CL_OUT_OF_HOST_MEMORY)?;
+        let dst_size: i32 = dst_size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let tx_dst = pipe.buffer_map(dst_res, 0, dst_size, RWFlags::W, true);
+
+        sw_copy(
+            tx_src.ptr(),
+            tx_dst.ptr(),
+            &region,
+            &CLVec::default(),
+            tx_src.stride() as usize,
+            tx_src.layer_stride() as usize,
+            &CLVec::new([dst_offset, 0, 0]),
+            row_pitch,
+            slice_pitch,
+        );
+
+        Ok(())
+    }
 }
 
 impl Drop for Context {
@@ -0,0 +1,163 @@
+extern crate rusticl_opencl_gen;
+
+use crate::api::icd::*;
+use crate::api::util::bit_check;
+use crate::api::util::cl_error_name;
+use crate::api::util::is_cl_error;
+use crate::core::context::*;
+use crate::core::queue::*;
+use crate::impl_cl_type_trait;
+
+use self::rusticl_opencl_gen::*;
+
+use std::ffi::CString;
+use std::slice;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+
+pub type EventSig = Box<dyn FnOnce(&Arc<Queue>) -> CLResult<()> + Send>;
+
+#[repr(C)]
+pub struct Event {
+    pub base: CLObjectBase<CL_INVALID_EVENT>,
+    pub context: Arc<Context>,
+    pub queue: Arc<Queue>,
+    pub cmd_type: cl_command_type,
+    pub deps: Vec<Arc<Event>>,
+    work: Mutex<Option<EventSig>>,
+    status: Mutex<cl_int>,
+    cv: Condvar,
+    // `clGetEventProfilingInfo` timestamps, in nanoseconds; 0 means "not yet recorded". Only ever
+    // populated when `queue` was created with CL_QUEUE_PROFILING_ENABLE -- see `Queue::queue`,
+    // `Queue::flush` and `run_event` for where each one gets stamped.
+    queued: AtomicU64,
+    submit: AtomicU64,
+    start: AtomicU64,
+    end: AtomicU64,
+}
+
+impl_cl_type_trait!(cl_event, Event, CL_INVALID_EVENT);
+
+impl Event {
+    pub fn new(
+        q: &Arc<Queue>,
+        cmd_type: cl_command_type,
+        deps: Vec<Arc<Event>>,
+        work: EventSig,
+    ) -> Arc<Self> {
+        Arc::new(Self {
+            base: CLObjectBase::new(),
+            context: q.context.clone(),
+            queue: q.clone(),
+            cmd_type: cmd_type,
+            deps: deps,
+            work: Mutex::new(Some(work)),
+            status: Mutex::new(CL_QUEUED as cl_int),
+            cv: Condvar::new(),
+            queued: AtomicU64::new(0),
+            submit: AtomicU64::new(0),
+            start: AtomicU64::new(0),
+            end: AtomicU64::new(0),
+        })
+    }
+
+    pub fn from_cl_arr(events: *const cl_event, num_events: u32) -> CLResult<Vec<Arc<Event>>> {
+        if !events.is_null() && num_events > 0 {
+            let s = unsafe { slice::from_raw_parts(events, num_events as usize) };
+            s.iter().map(|e| e.get_arc()).collect()
+        } else {
+            Ok(Vec::default())
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        *self.status.lock().unwrap() < 0
+    }
+
+    // Runs the event's work closure (if any) and parks the resulting status for `wait`. Not
+    // reached for an event whose dependency failed: `run_event` sets its status through
+    // `set_user_status` instead of calling this.
+    pub fn call(&self) {
+        let work = self.work.lock().unwrap().take();
+        let status = match work.map(|w| w(&self.queue)) {
+            Some(Err(e)) => e,
+            _ => CL_COMPLETE as cl_int,
+        };
+
+        // A failure here isn't tied to any `clEnqueueX` return code -- the app already got
+        // CL_SUCCESS back when this was queued -- so it's exactly the kind of asynchronous error
+        // `pfn_notify` exists for.
+        if is_cl_error(status) {
+            if let Ok(errinfo) = CString::new(format!(
+                "command type {} failed asynchronously with {}",
+                self.cmd_type,
+                cl_error_name(status).to_string_lossy()
+            )) {
+                self.context.notify(&errinfo, &[]);
+            }
+        }
+
+        self.set_status(status);
+    }
+
+    pub fn set_user_status(&self, status: cl_int) {
+        self.set_status(status);
+    }
+
+    fn set_status(&self, status: cl_int) {
+        *self.status.lock().unwrap() = status;
+        self.cv.notify_all();
+    }
+
+    // Blocks until the event has left CL_QUEUED/CL_SUBMITTED/CL_RUNNING, returning the terminal
+    // status (CL_COMPLETE, or a negative `cl_int` error code).
+    pub fn wait(&self) -> cl_int {
+        let mut status = self.status.lock().unwrap();
+        while *status > CL_COMPLETE as cl_int {
+            status = self.cv.wait(status).unwrap();
+        }
+        *status
+    }
+
+    pub fn set_queued(&self, ts: u64) {
+        self.queued.store(ts, Ordering::Relaxed);
+    }
+
+    pub fn set_submit(&self, ts: u64) {
+        self.submit.store(ts, Ordering::Relaxed);
+    }
+
+    pub fn set_start(&self, ts: u64) {
+        self.start.store(ts, Ordering::Relaxed);
+    }
+
+    pub fn set_end(&self, ts: u64) {
+        self.end.store(ts, Ordering::Relaxed);
+    }
+
+    // Backs `clGetEventProfilingInfo`. CL_PROFILING_INFO_NOT_AVAILABLE if `queue` wasn't created
+    // with CL_QUEUE_PROFILING_ENABLE, or if this particular timestamp hasn't been stamped yet
+    // (e.g. START/END queried before the command has actually run).
+    pub fn profiling_info(&self, name: cl_profiling_info) -> CLResult<cl_ulong> {
+        if !bit_check(self.queue.props, CL_QUEUE_PROFILING_ENABLE) {
+            return Err(CL_PROFILING_INFO_NOT_AVAILABLE);
+        }
+
+        let ts = match name {
+            CL_PROFILING_COMMAND_QUEUED => self.queued.load(Ordering::Relaxed),
+            CL_PROFILING_COMMAND_SUBMIT => self.submit.load(Ordering::Relaxed),
+            CL_PROFILING_COMMAND_START => self.start.load(Ordering::Relaxed),
+            CL_PROFILING_COMMAND_END => self.end.load(Ordering::Relaxed),
+            _ => return Err(CL_INVALID_VALUE),
+        };
+
+        if ts == 0 {
+            return Err(CL_PROFILING_INFO_NOT_AVAILABLE);
+        }
+
+        Ok(ts as cl_ulong)
+    }
+}
@@ -13,9 +13,12 @@ use crate::impl_cl_type_trait;
 
 use self::mesa_rust::compiler::clc::*;
 use self::mesa_rust::compiler::nir::*;
+use self::mesa_rust::pipe::context::*;
+use self::mesa_rust::pipe::resource::*;
 use self::mesa_rust_gen::*;
 use self::rusticl_opencl_gen::*;
 
+use std::cell::Cell;
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::collections::HashSet;
@@ -24,6 +27,7 @@ use std::os::raw::c_void;
 use std::ptr;
 use std::slice;
 use std::sync::Arc;
+use std::sync::Mutex;
 
 // ugh, we are not allowed to take refs, so...
 #[derive(Clone)]
@@ -33,6 +37,8 @@ pub enum KernelArgValue {
     MemObject(&'static Mem),
     Sampler(&'static Sampler),
     LocalMem(usize),
+    // a raw SVM/device pointer set through `clSetKernelArgSVMPointer`.
+    Svm(usize),
 }
 
 #[derive(PartialEq, Eq, Clone)]
@@ -73,6 +79,10 @@ impl KernelArg {
             .variables_with_mode(
                 nir_variable_mode::nir_var_uniform | nir_variable_mode::nir_var_image,
             )
+            // `const sampler_t` constants lower to uniform vars of their own, outside the kernel's
+            // declared parameter list -- keep them out of the by-location lookup below so they
+            // can't collide with (or be mistaken for) a real argument.
+            .filter(|v| !v.data.sampler.is_inline_sampler)
             .map(|v| (v.data.location, v))
             .collect();
         let mut res = Vec::new();
@@ -118,6 +128,14 @@ impl KernelArg {
         for var in nir.variables_with_mode(
             nir_variable_mode::nir_var_uniform | nir_variable_mode::nir_var_image,
         ) {
+            // Inline samplers don't occupy a kernel-arg or internal-arg slot -- the driver backend
+            // bakes their addressing/filter/normalized-coords mode straight off the variable when
+            // it compiles the `tex` instructions referencing them, so there's no location to wire
+            // up here and no kernel input space to reserve.
+            if var.data.sampler.is_inline_sampler {
+                continue;
+            }
+
             if let Some(arg) = args.get_mut(var.data.location as usize) {
                 arg.offset = var.data.driver_location as usize;
                 arg.dead = false;
@@ -131,6 +149,90 @@ impl KernelArg {
     }
 }
 
+// Per-device state that's expensive to (re)create but constant across launches of the same
+// kernel: the lowered NIR, the compute-state object built from it, and the uploaded constant
+// buffer. Shared (via the `Arc<KernelDevState>` on `Kernel`) across every clone of a kernel, since
+// they all compile to the same code for a given device -- only the bound argument `values` differ
+// between clones. Out-of-order queues genuinely run independent `clEnqueueNDRangeKernel` calls
+// against the same `dev_state` concurrently on separate worker threads (see `core::queue`'s
+// worker pool), so this cache needs real synchronization, not just interior mutability: a `Mutex`
+// per field instead of `RefCell`/`Cell`.
+struct KernelDevStateInner {
+    nir: Arc<NirShader>,
+    // lazily created on first `launch`, and only rebuilt when a later launch needs a `cso` built
+    // for different `req_input_mem`/`req_local_mem` sizes; keeps around whichever queue's pipe
+    // context created it, so `Drop` can tear it down the same way a one-shot create/delete used to.
+    cso: Mutex<Option<(Arc<PipeContext>, *mut c_void, u32, u32)>>,
+    // `nir.get_constant_buffer()`'s contents never change between launches, so the uploaded
+    // resource is created once on first use instead of every enqueue.
+    constant_buffer: Mutex<Option<Arc<PipeResource>>>,
+    cs_info: Mutex<Option<pipe_compute_state_object_info>>,
+}
+
+impl Drop for KernelDevStateInner {
+    fn drop(&mut self) {
+        if let Some((pipe, cso, _, _)) = self.cso.lock().unwrap().take() {
+            pipe.delete_compute_state(cso);
+        }
+    }
+}
+
+impl KernelDevStateInner {
+    // `pipe_compute_state_object_info` (max threads/block, preferred simd width, ...) can only be
+    // asked of the driver once a `cso` actually exists for this NIR. Reuse one that `launch`
+    // already built when there is one; otherwise stand up a throwaway context/cso just long
+    // enough to query it (e.g. `clGetKernelWorkGroupInfo` called before the kernel ever ran) and
+    // tear it back down immediately, same as the one-shot contexts `Context`/`Mem` create for
+    // similarly out-of-band driver queries. Cached after the first call either way.
+    fn cs_info(&self, dev: &Device) -> pipe_compute_state_object_info {
+        if let Some(info) = *self.cs_info.lock().unwrap() {
+            return info;
+        }
+
+        let info = if let Some((pipe, cso, _, _)) = self.cso.lock().unwrap().as_ref() {
+            pipe.get_compute_state_info(*cso)
+        } else {
+            let pipe = dev.screen().create_context().unwrap();
+            let cso = pipe.create_compute_state(&self.nir, 0, self.nir.shared_size());
+            let info = pipe.get_compute_state_info(cso);
+            pipe.delete_compute_state(cso);
+            info
+        };
+
+        *self.cs_info.lock().unwrap() = Some(info);
+        info
+    }
+}
+
+struct KernelDevState {
+    states: HashMap<Arc<Device>, KernelDevStateInner>,
+}
+
+impl KernelDevState {
+    fn new(nirs: HashMap<Arc<Device>, NirShader>) -> Arc<Self> {
+        Arc::new(Self {
+            states: nirs
+                .into_iter()
+                .map(|(d, nir)| {
+                    (
+                        d,
+                        KernelDevStateInner {
+                            nir: Arc::new(nir),
+                            cso: Mutex::new(None),
+                            constant_buffer: Mutex::new(None),
+                            cs_info: Mutex::new(None),
+                        },
+                    )
+                })
+                .collect(),
+        })
+    }
+
+    fn get(&self, dev: &Arc<Device>) -> &KernelDevStateInner {
+        self.states.get(dev).unwrap()
+    }
+}
+
 #[repr(C)]
 pub struct Kernel {
     pub base: CLObjectBase<CL_INVALID_KERNEL>,
@@ -139,8 +241,16 @@ pub struct Kernel {
     pub args: Vec<KernelArg>,
     pub values: Vec<RefCell<Option<KernelArgValue>>>,
     pub work_group_size: [usize; 3],
+    // space-separated `__attribute__((...))` qualifiers recognized from the kernel source, for
+    // CL_KERNEL_ATTRIBUTES.
+    pub attributes_string: String,
+    // pointers registered through `clSetKernelExecInfo(CL_KERNEL_EXEC_INFO_SVM_PTRS)`; these must
+    // be made resident for the duration of a launch even though they aren't bound kernel args.
+    pub svm_ptrs: RefCell<Vec<usize>>,
+    // `clSetKernelExecInfo(CL_KERNEL_EXEC_INFO_SVM_FINE_GRAIN_SYSTEM)`.
+    pub svm_fine_grain_system: Cell<bool>,
     internal_args: Vec<InternalKernelArg>,
-    nirs: HashMap<Arc<Device>, NirShader>,
+    dev_state: Arc<KernelDevState>,
 }
 
 impl_cl_type_trait!(cl_kernel, Kernel, CL_INVALID_KERNEL);
@@ -203,9 +313,11 @@ fn lower_and_optimize_nir_pre_inputs(dev: &Device, nir: &mut NirShader, lib_clc:
         );
         progress
     } {}
-    // TODO inline samplers
     // TODO variable initializers
     // TODO lower memcpy
+    // Moves `const sampler_t` constant initializers to the end of the shader so their addressing
+    // mode/filter mode/normalized-coordinates bits end up on the `nir_variable_data.sampler` field
+    // of an inline-sampler variable instead of being DCE'd away as dead constant math.
     nir.pass0(nir_move_inline_samplers_to_end);
     nir.pass2(
         nir_lower_vars_to_explicit_types,
@@ -215,7 +327,7 @@ fn lower_and_optimize_nir_pre_inputs(dev: &Device, nir: &mut NirShader, lib_clc:
 
     let mut printf_opts = nir_lower_printf_options::default();
     printf_opts.set_treat_doubles_as_floats(false);
-    printf_opts.max_buffer_size = dev.printf_buffer_size() as u32;
+    printf_opts.max_buffer_size = printf_buffer_size(dev);
     nir.pass1(nir_lower_printf, &printf_opts);
 
     nir.pass0(nir_split_var_copies);
@@ -227,12 +339,41 @@ fn lower_and_optimize_nir_pre_inputs(dev: &Device, nir: &mut NirShader, lib_clc:
     nir.pass0(nir_opt_deref);
 }
 
+// `dev.printf_buffer_size()`'s default is tuned for typical kernels; compute-heavy kernels that
+// emit a lot of output can hit it and silently lose data, so let `RUSTICL_PRINTF_BUFFER_SIZE`
+// (bytes) override it process-wide. Used both when lowering (`nir_lower_printf_options`, which
+// bakes the bound into the shader) and when allocating the buffer at launch time, so the two never
+// disagree. The buffer always starts with a 4-byte write-offset header, so clamp to at least that
+// many bytes -- anything smaller would make the header write itself an out-of-bounds access.
+fn printf_buffer_size(dev: &Device) -> u32 {
+    std::env::var("RUSTICL_PRINTF_BUFFER_SIZE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or_else(|| dev.printf_buffer_size() as u32)
+        .max(4)
+}
+
+// Size in bytes of a device pointer, as exposed through kernel args and internal args.
+fn dev_ptr_size(dev: &Device) -> usize {
+    if dev.address_bits() == 32 {
+        4
+    } else {
+        8
+    }
+}
+
 fn lower_and_optimize_nir_late(
     dev: &Device,
     nir: &mut NirShader,
     args: usize,
 ) -> Vec<InternalKernelArg> {
     let mut res = Vec::new();
+    let ptr_size = dev_ptr_size(dev);
+    let address_format = if ptr_size == 4 {
+        nir_address_format::nir_address_format_32bit_global
+    } else {
+        nir_address_format::nir_address_format_64bit_global
+    };
     let nir_options = unsafe {
         &*dev
             .screen
@@ -247,7 +388,10 @@ fn lower_and_optimize_nir_late(
             | nir_variable_mode::nir_var_function_temp,
         ptr::null(),
     );
-    // TODO inline samplers
+    // `tex` instructions that deref an inline-sampler variable carry its addressing/filter/
+    // normalized-coordinates mode with them from here on, so no separate sampler lowering pass is
+    // needed -- just make sure one never gets treated as a regular kernel/internal arg (see
+    // `KernelArg::assign_locations`).
     nir.pass1(nir_lower_readonly_images_to_tex, false);
     // TODO more image lowerings
     nir.pass2(
@@ -264,7 +408,6 @@ fn lower_and_optimize_nir_late(
     nir.extract_constant_initializers();
 
     // TODO printf
-    // TODO 32 bit devices
     // add vars for global offsets
     res.push(InternalKernelArg {
         kind: InternalKernelArgType::GlobalWorkOffsets,
@@ -277,15 +420,20 @@ fn lower_and_optimize_nir_late(
         args + res.len() - 1,
         "base_global_invocation_id",
     );
+    let addr_type = if ptr_size == 4 {
+        unsafe { glsl_uint_type() }
+    } else {
+        unsafe { glsl_uint64_t_type() }
+    };
     if nir.has_constant() {
         res.push(InternalKernelArg {
             kind: InternalKernelArgType::ConstantBuffer,
             offset: 0,
-            size: 8,
+            size: ptr_size,
         });
         lower_state.const_buf = nir.add_var(
             nir_variable_mode::nir_var_uniform,
-            unsafe { glsl_uint64_t_type() },
+            addr_type,
             args + res.len() - 1,
             "constant_buffer_addr",
         );
@@ -294,11 +442,11 @@ fn lower_and_optimize_nir_late(
         res.push(InternalKernelArg {
             kind: InternalKernelArgType::PrintfBuffer,
             offset: 0,
-            size: 8,
+            size: ptr_size,
         });
         lower_state.printf_buf = nir.add_var(
             nir_variable_mode::nir_var_uniform,
-            unsafe { glsl_uint64_t_type() },
+            addr_type,
             args + res.len() - 1,
             "printf_buffer_addr",
         );
@@ -315,7 +463,7 @@ fn lower_and_optimize_nir_late(
     nir.pass2(
         nir_lower_explicit_io,
         nir_variable_mode::nir_var_mem_global | nir_variable_mode::nir_var_mem_constant,
-        nir_address_format::nir_address_format_64bit_global,
+        address_format,
     );
     nir.pass0(nir_lower_system_values);
     let mut compute_options = nir_lower_compute_system_values_options::default();
@@ -373,6 +521,16 @@ impl Kernel {
         let nir = nirs.values_mut().next().unwrap();
         let wgs = nir.workgroup_size();
         let work_group_size = [wgs[0] as usize, wgs[1] as usize, wgs[2] as usize];
+        // of the qualifiers CTS cares about, only `reqd_work_group_size` survives into NIR as of
+        // now; `work_group_size_hint` and `vec_type_hint` aren't carried by our NIR metadata yet.
+        let attributes_string = if work_group_size != [0, 0, 0] {
+            format!(
+                "reqd_work_group_size({},{},{})",
+                work_group_size[0], work_group_size[1], work_group_size[2]
+            )
+        } else {
+            String::new()
+        };
         let mut args = KernelArg::from_spirv_nir(args, nir);
         // can't use vec!...
         let values = args.iter().map(|_| RefCell::new(None)).collect();
@@ -393,10 +551,13 @@ impl Kernel {
             name: name,
             args: args,
             work_group_size: work_group_size,
+            attributes_string: attributes_string,
             values: values,
+            svm_ptrs: RefCell::new(Vec::new()),
+            svm_fine_grain_system: Cell::new(false),
             internal_args: internal_args,
             // caller has to verify all kernels have the same sig
-            nirs: nirs,
+            dev_state: KernelDevState::new(nirs),
         })
     }
 
@@ -410,33 +571,30 @@ impl Kernel {
         grid: &[usize],
         offsets: &[usize],
     ) -> EventSig {
-        let nir = self.nirs.get(&q.device).unwrap();
-        let mut block = create_kernel_arr::<u32>(block, 1);
+        let state = self.dev_state.get(&q.device);
+        let nir = &state.nir;
+        let block = create_kernel_arr::<u32>(block, 1);
         let mut grid = create_kernel_arr::<u32>(grid, 1);
         let offsets = create_kernel_arr::<u64>(offsets, 0);
         let mut input: Vec<u8> = Vec::new();
         let mut resource_info = Vec::new();
         let mut local_size: u32 = nir.shared_size();
-        let printf_size = q.device.printf_buffer_size() as u32;
-
+        let printf_size = printf_buffer_size(&q.device);
+        // buffer/SVM pointer args and the internal constant/printf buffer pointers are all as wide
+        // as the device's address space, not hard-coded to 64 bit.
+        let ptr_size = dev_ptr_size(&q.device);
+
+        // the caller already resolved `block` to a non-zero, conformant local work-group size.
+        // Round the number of work-groups up rather than requiring an even divisor: when the
+        // global size isn't a multiple of the work-group size, the last work-group per dimension
+        // is partial. `last_block` (0 meaning "full-size", matching every other dimension) tells
+        // the driver how many of its invocations are actually in bounds, so it can mask off the
+        // rest -- this is what backs `CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT`.
+        let mut last_block = [0u32; 3];
         for i in 0..3 {
-            if block[i] == 0 {
-                if i == 0 {
-                    // TODO: make this more nice, but at least that should work
-                    let threads = q.device.max_block_sizes()[i] as u32;
-                    if grid[0] % threads == 0 {
-                        block[i] = threads;
-                        grid[i] /= threads;
-                    } else {
-                        block[i] = 1;
-                    }
-                } else {
-                    block[i] = 1;
-                }
-            } else {
-                // we already made sure everything is fine
-                grid[i] /= block[i];
-            }
+            let rem = grid[i] % block[i];
+            grid[i] = (grid[i] + block[i] - 1) / block[i];
+            last_block[i] = rem;
         }
 
         for (arg, val) in self.args.iter().zip(&self.values) {
@@ -447,20 +605,29 @@ impl Kernel {
             match val.borrow().as_ref().unwrap() {
                 KernelArgValue::Constant(c) => input.extend_from_slice(&c),
                 KernelArgValue::MemObject(mem) => {
-                    input.extend_from_slice(&mem.offset.to_ne_bytes());
+                    input.extend_from_slice(&(mem.offset as u64).to_ne_bytes()[..ptr_size]);
                     resource_info.push((Some(mem.get_res_of_dev(&q.device).clone()), arg.offset));
                 }
                 KernelArgValue::LocalMem(size) => {
-                    // TODO 32 bit
-                    input.extend_from_slice(&[0; 8]);
+                    input.extend_from_slice(&vec![0; ptr_size]);
                     local_size += *size as u32;
                 }
+                // SVM pointers are already device-addressable, so just write the raw address.
+                KernelArgValue::Svm(ptr) => {
+                    input.extend_from_slice(&(*ptr as u64).to_ne_bytes()[..ptr_size])
+                }
+                KernelArgValue::Sampler(_) => {
+                    // a sampler arg gets a zeroed pointer-sized slot in the kernel input and no
+                    // resource binding; matching on it here keeps it out of the catch-all panic
+                    // below.
+                    input.extend_from_slice(&vec![0; ptr_size]);
+                }
                 KernelArgValue::None => {
                     assert!(
                         arg.kind == KernelArgType::MemGlobal
                             || arg.kind == KernelArgType::MemConstant
                     );
-                    input.extend_from_slice(&[0; 8]);
+                    input.extend_from_slice(&vec![0; ptr_size]);
                 }
                 _ => panic!("unhandled arg type"),
             }
@@ -471,16 +638,27 @@ impl Kernel {
             input.append(&mut vec![0; arg.offset - input.len()]);
             match arg.kind {
                 InternalKernelArgType::ConstantBuffer => {
-                    input.extend_from_slice(&[0; 8]);
-                    let buf = nir.get_constant_buffer();
-                    let res = Arc::new(
-                        q.device
-                            .screen()
-                            .resource_create_buffer(buf.len() as u32)
-                            .unwrap(),
-                    );
-                    q.context()
-                        .buffer_subdata(&res, 0, buf.as_ptr().cast(), buf.len() as u32);
+                    input.extend_from_slice(&vec![0; ptr_size]);
+                    // `nir.get_constant_buffer()` is a compile-time constant, so the uploaded
+                    // resource is the same for every launch of this kernel on this device --
+                    // build and upload it once, then just reuse the cached resource.
+                    let res = state
+                        .constant_buffer
+                        .lock()
+                        .unwrap()
+                        .get_or_insert_with(|| {
+                            let buf = nir.get_constant_buffer();
+                            let res = Arc::new(
+                                q.device
+                                    .screen()
+                                    .resource_create_buffer(buf.len() as u32)
+                                    .unwrap(),
+                            );
+                            q.context()
+                                .buffer_subdata(&res, 0, buf.as_ptr().cast(), buf.len() as u32);
+                            res
+                        })
+                        .clone();
                     resource_info.push((Some(res), arg.offset));
                 }
                 InternalKernelArgType::GlobalWorkOffsets => {
@@ -490,7 +668,7 @@ impl Kernel {
                     let buf =
                         Arc::new(q.device.screen.resource_create_buffer(printf_size).unwrap());
 
-                    input.extend_from_slice(&[0; 8]);
+                    input.extend_from_slice(&vec![0; ptr_size]);
                     resource_info.push((Some(buf.clone()), arg.offset));
 
                     printf_buf = Some(buf);
@@ -500,7 +678,8 @@ impl Kernel {
 
         let k = self.clone();
         Box::new(move |q| {
-            let nir = k.nirs.get(&q.device).unwrap();
+            let state = k.dev_state.get(&q.device);
+            let nir = &state.nir;
             let mut input = input.clone();
             let mut resources = Vec::with_capacity(resource_info.len());
             let mut globals: Vec<*mut u32> = Vec::new();
@@ -513,7 +692,9 @@ impl Kernel {
             }
 
             if let Some(printf_buf) = &printf_buf {
-                let init_data: [u8; 1] = [4];
+                // the buffer starts with a 4-byte write offset, initialized just past the header
+                // itself; the shader atomically advances it as it writes entries.
+                let init_data = 4u32.to_ne_bytes();
                 q.context().buffer_subdata(
                     &printf_buf,
                     0,
@@ -521,16 +702,39 @@ impl Kernel {
                     init_data.len() as u32,
                 );
             }
-            let cso = q
-                .context()
-                .create_compute_state(nir, input.len() as u32, local_size);
+            // Reuse the cached cso as long as it was built for the same input/local mem sizes;
+            // otherwise tear down the stale one (if any) and build a fresh one. The cache outlives
+            // this single launch (it's keyed off `k.dev_state`, shared across every clone of this
+            // kernel), so a tight host-side enqueue loop stops paying CSO create/destroy per call.
+            let cso = {
+                let mut cso_cache = state.cso.lock().unwrap();
+                let reusable = cso_cache
+                    .as_ref()
+                    .filter(|(_, _, i, l)| *i == input.len() as u32 && *l == local_size)
+                    .map(|(_, cso, _, _)| *cso);
+
+                match reusable {
+                    Some(cso) => cso,
+                    None => {
+                        if let Some((old_pipe, old_cso, _, _)) = cso_cache.take() {
+                            old_pipe.delete_compute_state(old_cso);
+                        }
+                        let cso = q
+                            .context()
+                            .create_compute_state(nir, input.len() as u32, local_size);
+                        *state.cs_info.lock().unwrap() = Some(q.context().get_compute_state_info(cso));
+                        *cso_cache = Some((q.context().clone(), cso, input.len() as u32, local_size));
+                        cso
+                    }
+                }
+            };
 
             q.context().bind_compute_state(cso);
             q.context()
                 .set_global_binding(resources.as_slice(), &mut globals);
-            q.context().launch_grid(work_dim, block, grid, &input);
+            q.context()
+                .launch_grid(work_dim, block, grid, last_block, &input);
             q.context().clear_global_binding(globals.len() as u32);
-            q.context().delete_compute_state(cso);
             q.context().memory_barrier(PIPE_BARRIER_GLOBAL_BUFFER);
 
             if let Some(printf_buf) = &printf_buf {
@@ -541,8 +745,19 @@ impl Kernel {
                     unsafe { slice::from_raw_parts(tx.ptr().cast(), printf_size as usize) };
                 let length = u32::from_ne_bytes(*extract(&mut buf));
 
-                // update our slice to make sure we don't go out of bounds
-                buf = &buf[0..(length - 4) as usize];
+                // `length` is the shader's view of how much it wrote (including the 4-byte
+                // header), which can exceed `printf_size` if the kernel produced more output than
+                // the buffer holds -- the shader stops writing entries once it would overflow, but
+                // the counter itself keeps counting past that point. Clamp before slicing so an
+                // overflowing kernel can't panic the host, and let the app know it lost output.
+                let written = length.saturating_sub(4).min(printf_size - 4) as usize;
+                if length > printf_size {
+                    eprintln!(
+                        "rusticl: printf buffer overflow, dropped {} byte(s) of output",
+                        length - printf_size
+                    );
+                }
+                buf = &buf[0..written];
 
                 unsafe {
                     u_printf(buf.as_ptr().cast(), buf.len(), printf_format.as_ptr());
@@ -618,12 +833,44 @@ impl Kernel {
     }
 
     pub fn priv_mem_size(&self, dev: &Arc<Device>) -> cl_ulong {
-        self.nirs.get(dev).unwrap().scratch_size() as cl_ulong
+        self.dev_state.get(dev).nir.scratch_size() as cl_ulong
     }
 
     pub fn local_mem_size(&self, dev: &Arc<Device>) -> cl_ulong {
-        // TODO include args
-        self.nirs.get(dev).unwrap().shared_size() as cl_ulong
+        // `MemLocal` args are sized by the host at `clSetKernelArg` time (their NIR variable has
+        // no fixed size), so they don't show up in `nir.shared_size()` -- add them up separately.
+        let args_local_mem: usize = self
+            .values
+            .iter()
+            .filter_map(|v| match v.borrow().as_ref() {
+                Some(KernelArgValue::LocalMem(size)) => Some(*size),
+                _ => None,
+            })
+            .sum();
+
+        self.dev_state.get(dev).nir.shared_size() as cl_ulong + args_local_mem as cl_ulong
+    }
+
+    // CL_KERNEL_WORK_GROUP_SIZE: the driver's actual per-CSO limit for this kernel, further
+    // clamped by the device's usual cap and by however many threads worth of this kernel's shared
+    // memory footprint actually fit in the device's local memory.
+    pub fn max_threads_per_block(&self, dev: &Arc<Device>) -> usize {
+        let state = self.dev_state.get(dev);
+        let mut threads = (state.cs_info(dev).max_threads as usize).max(1);
+        threads = threads.min(dev.max_work_group_size());
+
+        let local_mem_used = self.local_mem_size(dev);
+        if local_mem_used > 0 {
+            let local_mem_limited = dev.local_mem_size() / local_mem_used;
+            threads = threads.min(local_mem_limited as usize);
+        }
+
+        threads.max(1)
+    }
+
+    // CL_KERNEL_PREFERRED_WORK_GROUP_SIZE_MULTIPLE.
+    pub fn preferred_simd_size(&self, dev: &Arc<Device>) -> usize {
+        self.dev_state.get(dev).cs_info(dev).preferred_simd_size as usize
     }
 }
 
@@ -636,8 +883,13 @@ impl Clone for Kernel {
             args: self.args.clone(),
             values: self.values.clone(),
             work_group_size: self.work_group_size.clone(),
+            attributes_string: self.attributes_string.clone(),
+            svm_ptrs: self.svm_ptrs.clone(),
+            svm_fine_grain_system: self.svm_fine_grain_system.clone(),
             internal_args: self.internal_args.clone(),
-            nirs: self.nirs.clone(),
+            // shared, not deep-copied: every clone compiles to the same code per device, so they
+            // can all reuse one cached cso/constant buffer instead of each paying to rebuild them.
+            dev_state: self.dev_state.clone(),
         }
     }
 }
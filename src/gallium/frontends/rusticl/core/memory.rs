@@ -1,4 +1,5 @@
 extern crate mesa_rust;
+extern crate mesa_rust_gen;
 extern crate rusticl_opencl_gen;
 
 use crate::api::icd::*;
@@ -9,8 +10,10 @@ use crate::core::device::*;
 use crate::core::queue::*;
 use crate::impl_cl_type_trait;
 
+use self::mesa_rust::pipe::context::RWFlags;
 use self::mesa_rust::pipe::resource::*;
 use self::mesa_rust::pipe::transfer::*;
+use self::mesa_rust_gen::*;
 use self::rusticl_opencl_gen::*;
 
 use std::collections::HashMap;
@@ -31,16 +34,122 @@ pub struct Mem {
     pub offset: usize,
     pub host_ptr: *mut c_void,
     pub image_format: cl_image_format,
+    // resolved once at `clCreateImage` time so later read/write/copy lowering can reuse it instead
+    // of re-deriving it (and potentially hitting the same `to_pipe_format` panic) on every call.
+    pub pipe_format: pipe_format,
     pub image_desc: cl_image_desc,
     pub image_elem_size: u8,
+    // `clSetMemObjectDestructorCallback` registrations, fired in reverse registration order from
+    // `Drop` once the object's last reference is released.
     pub cbs: Mutex<Vec<Box<dyn Fn(cl_mem) -> ()>>>,
     res: Option<HashMap<*const Device, PipeResource>>,
-    maps: Mutex<HashMap<*mut c_void, PipeTransfer>>,
+    maps: Mutex<Mappings>,
 }
 
 impl_cl_type_trait!(cl_mem, Mem, CL_INVALID_MEM_OBJECT);
 
-fn sw_copy(
+// One live `PipeTransfer` per device, shared by every outstanding `map` that falls inside the
+// mapped region. OpenCL explicitly allows mapping overlapping regions of the same buffer, so a
+// second `map` call that fits inside an already-mapped range just bumps `count` instead of
+// creating its own transfer; `unmap` only tears the transfer down once `count` reaches zero.
+//
+// `shadow` is set when the real resource isn't `is_host_visible`: `tx` then maps a staging
+// resource instead, populated with the real resource's contents up front and, for writable maps,
+// copied back to the real resource on the final `unmap`.
+struct Mapping {
+    tx: PipeTransfer,
+    shadow: Option<PipeResource>,
+    offset: usize,
+    size: usize,
+    count: u32,
+    writable: bool,
+}
+
+// Per-`Mem` map bookkeeping, keyed first by device, where each device can have several
+// independently-tracked `Mapping` regions live at once: a `map` call only coalesces into an
+// existing `Mapping` it falls entirely inside of, and otherwise gets its own -- a disjoint,
+// overlapping-but-not-contained, or simply non-overlapping second map on the same device is a
+// second live `Mapping`, not a replacement for the first (the OpenCL spec allows all of those).
+//
+// `ptr_to_device` lets `unmap` find the right device's `Vec<Mapping>` in O(1); from there it uses
+// `outstanding`'s recorded offset/size for that pointer to pick out which `Mapping` of that
+// device's list it belongs to.
+//
+// `outstanding` tracks every live `clEnqueueMapBuffer` region by the pointer returned to the
+// application, independent of the device-keyed coalescing above, so `has_writable_overlap` can
+// reject a new writable map that overlaps any still-unmapped region per the spec.
+#[derive(Default)]
+struct Mappings {
+    by_device: HashMap<*const Device, Vec<Mapping>>,
+    ptr_to_device: HashMap<usize, *const Device>,
+    outstanding: HashMap<usize, (usize, usize, bool)>,
+}
+
+// Extent (on the same x/y/z axes `pipe_box` uses) an `origin`/`region` pair may legally reach for
+// a given `mem_type`. Buffers only ever use `x`/`width`; for array images the CL array index
+// already lines up with the axis `pipe_box` has left over (`y`/`height` for `IMAGE1D_ARRAY`,
+// `z`/`depth` for `IMAGE2D_ARRAY`), so no remapping is needed there either.
+pub(super) fn box_extent(
+    mem_type: cl_mem_object_type,
+    image_desc: &cl_image_desc,
+    size: usize,
+) -> CLVec<usize> {
+    match mem_type {
+        CL_MEM_OBJECT_IMAGE1D | CL_MEM_OBJECT_IMAGE1D_BUFFER => {
+            CLVec::new([image_desc.image_width, 1, 1])
+        }
+        CL_MEM_OBJECT_IMAGE1D_ARRAY => {
+            CLVec::new([image_desc.image_width, image_desc.image_array_size, 1])
+        }
+        CL_MEM_OBJECT_IMAGE2D => CLVec::new([image_desc.image_width, image_desc.image_height, 1]),
+        CL_MEM_OBJECT_IMAGE2D_ARRAY => CLVec::new([
+            image_desc.image_width,
+            image_desc.image_height,
+            image_desc.image_array_size,
+        ]),
+        CL_MEM_OBJECT_IMAGE3D => CLVec::new([
+            image_desc.image_width,
+            image_desc.image_height,
+            image_desc.image_depth,
+        ]),
+        // plain buffers (and sub-buffers) only ever use x/width.
+        _ => CLVec::new([size, 1, 1]),
+    }
+}
+
+// Builds the canonical `pipe_box` for an `origin`/`region` pair, clamping so the result never
+// collapses to a zero extent (which would perform no transfer) nor reaches past the resource's
+// own bounds. Centralizes what used to be a handful of scattered, error-prone manual
+// `pipe_box { .. }` literals that risked out-of-bounds transfers if `origin`/`region` were ever
+// slightly off.
+pub(super) fn create_pipe_box(
+    origin: CLVec<usize>,
+    region: CLVec<usize>,
+    mem_type: cl_mem_object_type,
+    image_desc: &cl_image_desc,
+    size: usize,
+) -> pipe_box {
+    let extent = box_extent(mem_type, image_desc, size);
+    let mut o = [0usize; 3];
+    let mut r = [0usize; 3];
+
+    for i in 0..3 {
+        o[i] = origin[i].min(extent[i].saturating_sub(1));
+        r[i] = region[i].clamp(1, (extent[i] - o[i]).max(1));
+    }
+
+    pipe_box {
+        x: o[0] as i32,
+        y: o[1] as i32,
+        z: o[2] as i32,
+        width: r[0] as i32,
+        height: r[1] as i32,
+        depth: r[2] as i32,
+        ..Default::default()
+    }
+}
+
+pub(super) fn sw_copy(
     src: *const c_void,
     dst: *mut c_void,
     region: &CLVec<usize>,
@@ -71,16 +180,36 @@ impl Mem {
         size: usize,
         host_ptr: *mut c_void,
     ) -> CLResult<Arc<Mem>> {
-        if bit_check(flags, CL_MEM_COPY_HOST_PTR | CL_MEM_ALLOC_HOST_PTR) {
-            println!("host ptr semantics not implemented!");
-        }
+        // CL_MEM_ALLOC_HOST_PTR asks for a host-visible resource up front, so the eventual `map`
+        // can hand back its pointer directly instead of shadow-copying; everything else is happy
+        // with an ordinary device-local allocation.
+        let res_type = if bit_check(flags, CL_MEM_ALLOC_HOST_PTR) {
+            ResourceType::Cached
+        } else {
+            ResourceType::Normal
+        };
 
+        // CL_MEM_USE_HOST_PTR resources are created straight from `host_ptr`, so every later
+        // map/unmap and rect read/write has to keep operating on that same memory instead of a
+        // separate allocation (see `map`/`unmap`/`*_rect` below). CL_MEM_COPY_HOST_PTR (alone or
+        // combined with ALLOC_HOST_PTR) additionally uploads `host_ptr`'s contents into it here.
         let buffer = if bit_check(flags, CL_MEM_USE_HOST_PTR) {
-            context.create_buffer_from_user(size, host_ptr)
+            context.create_buffer_from_user(size, host_ptr, res_type)
         } else {
-            context.create_buffer(size)
+            context.create_buffer(size, res_type)
         }?;
 
+        if bit_check(flags, CL_MEM_COPY_HOST_PTR) {
+            let upload_size: u32 = size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+            for dev in &context.devs {
+                let res = buffer.get(dev).unwrap();
+                dev.screen()
+                    .create_context()
+                    .ok_or(CL_OUT_OF_HOST_MEMORY)?
+                    .buffer_subdata(res, 0, host_ptr, upload_size);
+            }
+        }
+
         Ok(Arc::new(Self {
             base: CLObjectBase::new(),
             context: context.clone(),
@@ -91,11 +220,12 @@ impl Mem {
             offset: 0,
             host_ptr: host_ptr,
             image_format: cl_image_format::default(),
+            pipe_format: pipe_format::PIPE_FORMAT_NONE,
             image_desc: cl_image_desc::default(),
             image_elem_size: 0,
             cbs: Mutex::new(Vec::new()),
             res: Some(buffer),
-            maps: Mutex::new(HashMap::new()),
+            maps: Mutex::new(Mappings::default()),
         }))
     }
 
@@ -115,11 +245,12 @@ impl Mem {
             offset: offset,
             host_ptr: unsafe { parent.host_ptr.offset(offset as isize) },
             image_format: cl_image_format::default(),
+            pipe_format: pipe_format::PIPE_FORMAT_NONE,
             image_desc: cl_image_desc::default(),
             image_elem_size: 0,
             cbs: Mutex::new(Vec::new()),
             res: None,
-            maps: Mutex::new(HashMap::new()),
+            maps: Mutex::new(Mappings::default()),
         })
     }
 
@@ -128,39 +259,104 @@ impl Mem {
         mem_type: cl_mem_object_type,
         flags: cl_mem_flags,
         image_format: &cl_image_format,
+        pipe_format: pipe_format,
         image_desc: cl_image_desc,
         image_elem_size: u8,
         host_ptr: *mut c_void,
-    ) -> Arc<Mem> {
-        if bit_check(
-            flags,
-            CL_MEM_USE_HOST_PTR | CL_MEM_COPY_HOST_PTR | CL_MEM_ALLOC_HOST_PTR,
-        ) {
-            println!("host ptr semantics not implemented!");
+        source: Option<Arc<Mem>>,
+    ) -> CLResult<Arc<Mem>> {
+        // Same host-pointer semantics as `new_buffer`, just backed by `create_texture[_from_user]`
+        // and `texture_map` instead of their buffer counterparts.
+        let res_type = if bit_check(flags, CL_MEM_ALLOC_HOST_PTR) {
+            ResourceType::Cached
+        } else {
+            ResourceType::Normal
+        };
+        // `source` is `Some` for both cl_khr_image2d_from_buffer (a buffer backing a 1D image
+        // buffer or 2D image) and image-from-image views (a 2D image reinterpreted with a
+        // compatible, different channel order). Either way there's nothing to upload even if
+        // CL_MEM_COPY_HOST_PTR is set, since host_ptr must be NULL whenever mem_object is given.
+        let texture = if let Some(source) = &source {
+            if source.is_buffer() {
+                context.create_texture_from_buffer(
+                    &image_desc,
+                    pipe_format,
+                    source.res.as_ref().unwrap(),
+                )?
+            } else {
+                // Image-from-image view: shares the same per-device textures as `source` instead
+                // of allocating new ones, so the two `Mem`s view the same data store.
+                source.res.as_ref().unwrap().clone()
+            }
+        } else if bit_check(flags, CL_MEM_USE_HOST_PTR) {
+            context.create_texture_from_user(&image_desc, pipe_format, host_ptr, res_type)?
+        } else {
+            context.create_texture(&image_desc, pipe_format, res_type)?
+        };
+
+        if source.is_none() && bit_check(flags, CL_MEM_COPY_HOST_PTR) {
+            let region = CLVec::new([
+                image_desc.image_width,
+                image_desc.image_height.max(1),
+                image_desc.image_depth.max(1),
+            ]);
+            context.write_image(
+                &texture,
+                mem_type,
+                &image_desc,
+                CLVec::default(),
+                region,
+                host_ptr,
+                image_desc.image_row_pitch,
+                image_desc.image_slice_pitch,
+            )?;
         }
 
-        Arc::new(Self {
+        Ok(Arc::new(Self {
             base: CLObjectBase::new(),
             context: context.clone(),
-            parent: None,
+            // keeps the backing buffer or source image (and its refcount) alive for as long as
+            // this image is, same as `new_sub_buffer` does for its parent buffer.
+            parent: source,
             mem_type: mem_type,
             flags: flags,
             size: 0,
             offset: 0,
             host_ptr: host_ptr,
             image_format: *image_format,
+            pipe_format: pipe_format,
             image_desc: image_desc,
             image_elem_size: image_elem_size,
             cbs: Mutex::new(Vec::new()),
-            res: None,
-            maps: Mutex::new(HashMap::new()),
-        })
+            res: Some(texture),
+            maps: Mutex::new(Mappings::default()),
+        }))
     }
 
     pub fn is_buffer(&self) -> bool {
         self.mem_type == CL_MEM_OBJECT_BUFFER
     }
 
+    /// Builds the canonical `pipe_box` for an `origin`/`region` pair against this object. See the
+    /// free function of the same name for the clamping/axis-mapping rules.
+    pub fn create_pipe_box(&self, origin: CLVec<usize>, region: CLVec<usize>) -> pipe_box {
+        create_pipe_box(origin, region, self.mem_type, &self.image_desc, self.size)
+    }
+
+    // CL_INVALID_VALUE if `origin`/`region` reaches past this image's declared extent, i.e. the
+    // bounds check `clEnqueueCopyImageToBuffer`/`clEnqueueCopyBufferToImage`/`clEnqueueCopyImage`
+    // all require. Shares `box_extent` with `create_pipe_box` so the two can't disagree on what
+    // each axis means for a given `mem_type`.
+    pub fn check_bounds(&self, origin: &CLVec<usize>, region: &CLVec<usize>) -> CLResult<()> {
+        let extent = box_extent(self.mem_type, &self.image_desc, self.size);
+        for i in 0..3 {
+            if origin[i].checked_add(region[i]).ok_or(CL_INVALID_VALUE)? > extent[i] {
+                Err(CL_INVALID_VALUE)?
+            }
+        }
+        Ok(())
+    }
+
     pub fn has_same_parent(&self, other: &Self) -> bool {
         let a = self.parent.as_ref().map_or(self, |p| &p);
         let b = other.parent.as_ref().map_or(other, |p| &p);
@@ -176,6 +372,16 @@ impl Mem {
             .unwrap()
     }
 
+    // `Mappings` only keeps the raw `*const Device` it was keyed by (see its doc comment), so
+    // shadow copy-back needs this to recover the owning `Arc<Device>` and get back to its screen.
+    fn find_device(&self, dev: *const Device) -> &Arc<Device> {
+        self.context
+            .devs
+            .iter()
+            .find(|d| Arc::as_ptr(d) == dev)
+            .unwrap()
+    }
+
     pub fn write_from_user(
         &self,
         q: &Arc<Queue>,
@@ -194,6 +400,30 @@ impl Mem {
         Ok(())
     }
 
+    // Backs `clEnqueueFillBuffer`. `pattern` must point at exactly `pattern_size` bytes and is
+    // tiled by the driver across `[offset, offset + size)`; the caller is responsible for not
+    // invoking this with `size == 0`; the `clEnqueueFillBuffer` work closure returns early for a
+    // zero-length clear before it would call this.
+    pub fn fill(
+        &self,
+        q: &Arc<Queue>,
+        pattern: *const c_void,
+        pattern_size: usize,
+        offset: usize,
+        size: usize,
+    ) -> CLResult<()> {
+        // TODO support sub buffers
+        let r = self.get_res().get(&Arc::as_ptr(&q.device)).unwrap();
+        q.context().clear_buffer(
+            r,
+            offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+            size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+            pattern,
+            pattern_size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+        );
+        Ok(())
+    }
+
     pub fn write_from_user_rect(
         &self,
         src: *const c_void,
@@ -206,13 +436,33 @@ impl Mem {
         dst_row_pitch: usize,
         dst_slice_pitch: usize,
     ) -> CLResult<()> {
+        // CL_MEM_USE_HOST_PTR requires every access to land in `host_ptr` itself, so skip the
+        // resource map -- which would hand back a driver-owned pointer, not necessarily `host_ptr`
+        // -- and copy straight into it instead.
+        if bit_check(self.flags, CL_MEM_USE_HOST_PTR) {
+            sw_copy(
+                src,
+                self.host_ptr,
+                region,
+                src_origin,
+                src_row_pitch,
+                src_slice_pitch,
+                dst_origin,
+                dst_row_pitch,
+                dst_slice_pitch,
+            );
+            return Ok(());
+        }
+
         let r = self
             .res
             .as_ref()
             .unwrap()
             .get(&Arc::as_ptr(&q.device))
             .unwrap();
-        let tx = q.context().buffer_map(r, 0, self.size.try_into().unwrap());
+        let tx = q
+            .context()
+            .buffer_map(r, 0, self.size.try_into().unwrap(), RWFlags::W, true);
 
         sw_copy(
             src,
@@ -242,13 +492,31 @@ impl Mem {
         dst_row_pitch: usize,
         dst_slice_pitch: usize,
     ) -> CLResult<()> {
+        // See the matching comment in `write_from_user_rect`.
+        if bit_check(self.flags, CL_MEM_USE_HOST_PTR) {
+            sw_copy(
+                self.host_ptr,
+                dst,
+                region,
+                src_origin,
+                src_row_pitch,
+                src_slice_pitch,
+                dst_origin,
+                dst_row_pitch,
+                dst_slice_pitch,
+            );
+            return Ok(());
+        }
+
         let r = self
             .res
             .as_ref()
             .unwrap()
             .get(&Arc::as_ptr(&q.device))
             .unwrap();
-        let tx = q.context().buffer_map(r, 0, self.size.try_into().unwrap());
+        let tx = q
+            .context()
+            .buffer_map(r, 0, self.size.try_into().unwrap(), RWFlags::R, true);
 
         sw_copy(
             tx.ptr(),
@@ -291,14 +559,49 @@ impl Mem {
             .get(&Arc::as_ptr(&q.device))
             .unwrap();
 
+        // Rows (and slices) are tightly packed on both sides, so the whole region -- or at least
+        // whole rows/slices of it -- can be handed straight to the pipe driver via
+        // `resource_copy_region`, skipping the map + CPU copy entirely.
+        let rows_packed = src_row_pitch == region[0] && dst_row_pitch == region[0];
+        let slices_packed =
+            rows_packed && src_slice_pitch == region[0] * region[1] && dst_slice_pitch == region[0] * region[1];
+
+        if slices_packed {
+            let size = region[0] * region[1] * region[2];
+            let src_offset = *src_origin * [1, src_row_pitch, src_slice_pitch];
+            let dst_offset = *dst_origin * [1, dst_row_pitch, dst_slice_pitch];
+            q.context().resource_copy_region(
+                res_src,
+                src_offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                res_dst,
+                dst_offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+            );
+            return Ok(());
+        } else if rows_packed {
+            let size = region[0] * region[1];
+            for z in 0..region[2] {
+                let src_offset = (*src_origin + [0, 0, z]) * [1, src_row_pitch, src_slice_pitch];
+                let dst_offset = (*dst_origin + [0, 0, z]) * [1, dst_row_pitch, dst_slice_pitch];
+                q.context().resource_copy_region(
+                    res_src,
+                    src_offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                    res_dst,
+                    dst_offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                    size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                );
+            }
+            return Ok(());
+        }
+
+        // Mismatched pitches: fall back to mapping both sides and copying on the CPU.
         let tx_src = q
             .context()
-            .buffer_map(res_src, 0, self.size.try_into().unwrap());
+            .buffer_map(res_src, 0, self.size.try_into().unwrap(), RWFlags::R, true);
         let tx_dst = q
             .context()
-            .buffer_map(res_dst, 0, dst.size.try_into().unwrap());
+            .buffer_map(res_dst, 0, dst.size.try_into().unwrap(), RWFlags::W, true);
 
-        // TODO check to use hw accelerated paths (e.g. resource_copy_region or blits)
         sw_copy(
             tx_src.ptr(),
             tx_dst.ptr(),
@@ -317,26 +620,276 @@ impl Mem {
         Ok(())
     }
 
-    // TODO use PIPE_MAP_UNSYNCHRONIZED for non blocking
-    pub fn map(&self, q: &Arc<Queue>, offset: usize, size: usize) -> *mut c_void {
-        let res = self
+    // Maps an image region with `texture_map` and hands back the mapped pointer together with the
+    // row/slice pitches the driver actually used for it (which may differ from `image_desc`'s).
+    fn map_image_region(
+        &self,
+        q: &Arc<Queue>,
+        origin: &CLVec<usize>,
+        region: &CLVec<usize>,
+        rw: RWFlags,
+        block: bool,
+    ) -> PipeTransfer {
+        let res = self.get_res().get(&Arc::as_ptr(&q.device)).unwrap();
+        let b = self.create_pipe_box(*origin, *region);
+        q.context().texture_map(res, &b, rw, block)
+    }
+
+    pub fn copy_image_to_buffer(
+        &self,
+        dst: &Self,
+        q: &Arc<Queue>,
+        src_origin: &CLVec<usize>,
+        dst_offset: usize,
+        region: &CLVec<usize>,
+    ) -> CLResult<()> {
+        let bpp = self.image_elem_size as usize;
+        let res_dst = dst
             .res
             .as_ref()
             .unwrap()
             .get(&Arc::as_ptr(&q.device))
             .unwrap();
-        let tx = q
+
+        let tx_src = self.map_image_region(q, src_origin, region, RWFlags::R, true);
+        let tx_dst = q
             .context()
-            .buffer_map(res, offset.try_into().unwrap(), size.try_into().unwrap());
+            .buffer_map(res_dst, 0, dst.size.try_into().unwrap(), RWFlags::W, true);
+
+        let row_pitch = region[0] * bpp;
+        let slice_pitch = row_pitch * region[1];
+        sw_copy(
+            tx_src.ptr(),
+            tx_dst.ptr(),
+            &CLVec::new([row_pitch, region[1], region[2]]),
+            &CLVec::default(),
+            tx_src.stride() as usize,
+            tx_src.layer_stride() as usize,
+            &CLVec::new([dst_offset, 0, 0]),
+            row_pitch,
+            slice_pitch,
+        );
+
+        drop(tx_src);
+        drop(tx_dst);
+
+        Ok(())
+    }
+
+    pub fn copy_buffer_to_image(
+        &self,
+        dst: &Self,
+        q: &Arc<Queue>,
+        src_offset: usize,
+        dst_origin: &CLVec<usize>,
+        region: &CLVec<usize>,
+    ) -> CLResult<()> {
+        let bpp = dst.image_elem_size as usize;
+        let res_src = self
+            .res
+            .as_ref()
+            .unwrap()
+            .get(&Arc::as_ptr(&q.device))
+            .unwrap();
+
+        let tx_src = q
+            .context()
+            .buffer_map(res_src, 0, self.size.try_into().unwrap(), RWFlags::R, true);
+        let tx_dst = dst.map_image_region(q, dst_origin, region, RWFlags::W, true);
+
+        let row_pitch = region[0] * bpp;
+        let slice_pitch = row_pitch * region[1];
+        sw_copy(
+            tx_src.ptr(),
+            tx_dst.ptr(),
+            &CLVec::new([row_pitch, region[1], region[2]]),
+            &CLVec::new([src_offset, 0, 0]),
+            row_pitch,
+            slice_pitch,
+            &CLVec::default(),
+            tx_dst.stride() as usize,
+            tx_dst.layer_stride() as usize,
+        );
+
+        drop(tx_src);
+        drop(tx_dst);
+
+        Ok(())
+    }
+
+    pub fn copy_image(
+        &self,
+        dst: &Self,
+        q: &Arc<Queue>,
+        src_origin: &CLVec<usize>,
+        dst_origin: &CLVec<usize>,
+        region: &CLVec<usize>,
+    ) -> CLResult<()> {
+        let bpp = self.image_elem_size as usize;
+
+        let tx_src = self.map_image_region(q, src_origin, region, RWFlags::R, true);
+        let tx_dst = dst.map_image_region(q, dst_origin, region, RWFlags::W, true);
+
+        sw_copy(
+            tx_src.ptr(),
+            tx_dst.ptr(),
+            &CLVec::new([region[0] * bpp, region[1], region[2]]),
+            &CLVec::default(),
+            tx_src.stride() as usize,
+            tx_src.layer_stride() as usize,
+            &CLVec::default(),
+            tx_dst.stride() as usize,
+            tx_dst.layer_stride() as usize,
+        );
+
+        drop(tx_src);
+        drop(tx_dst);
+
+        Ok(())
+    }
+
+    // `offset`/`size` may overlap an already-outstanding mapping of the same region; see
+    // `has_writable_overlap` for the spec-mandated rejection of overlapping writable maps, which
+    // the caller is expected to check before calling this.
+    //
+    // TODO use PIPE_MAP_UNSYNCHRONIZED for non blocking
+    pub fn map(&self, q: &Arc<Queue>, offset: usize, size: usize, writable: bool) -> *mut c_void {
+        // CL_MEM_USE_HOST_PTR guarantees the application's own pointer is the one in use, so map
+        // it directly instead of going through a `PipeTransfer` that might hand back a different,
+        // driver-owned mapping of the same resource.
+        if bit_check(self.flags, CL_MEM_USE_HOST_PTR) {
+            let ptr = unsafe { self.host_ptr.add(offset) };
+            self.maps
+                .lock()
+                .unwrap()
+                .outstanding
+                .insert(ptr as usize, (offset, size, writable));
+            return ptr;
+        }
+
+        let dev = Arc::as_ptr(&q.device);
+        let mut maps = self.maps.lock().unwrap();
+
+        let coalesced = maps.by_device.get_mut(&dev).and_then(|mappings| {
+            mappings
+                .iter_mut()
+                .find(|m| offset >= m.offset && offset + size <= m.offset + m.size)
+                .map(|m| {
+                    m.count += 1;
+                    unsafe { m.tx.ptr().add(offset - m.offset) }
+                })
+        });
+        if let Some(ptr) = coalesced {
+            maps.ptr_to_device.insert(ptr as usize, dev);
+            maps.outstanding.insert(ptr as usize, (offset, size, writable));
+            return ptr;
+        }
+
+        let res = self.res.as_ref().unwrap().get(&dev).unwrap();
+        let adj_offset: i32 = offset.try_into().unwrap();
+        let adj_size: i32 = size.try_into().unwrap();
+
+        // Map a host-visible staging shadow instead of `res` itself when it can't be mapped
+        // directly, pre-populated with `res`'s current contents so reads through the returned
+        // pointer see live data; `unmap` copies writable maps back to `res` once `count` hits 0.
+        let (tx, shadow) = if res.is_host_visible() {
+            let tx = q
+                .context()
+                .buffer_map(res, adj_offset, adj_size, RWFlags::RW, true);
+            (tx, None)
+        } else {
+            let shadow = q
+                .device
+                .screen()
+                .resource_create_buffer(size.try_into().unwrap(), ResourceType::Staging)
+                .unwrap();
+            q.context()
+                .resource_copy_region(res, adj_offset, &shadow, 0, adj_size);
+            let tx = q
+                .context()
+                .buffer_map(&shadow, 0, adj_size, RWFlags::RW, true);
+            (tx, Some(shadow))
+        };
         let ptr = tx.ptr();
 
-        self.maps.lock().unwrap().insert(tx.ptr(), tx);
+        maps.by_device.entry(dev).or_default().push(Mapping {
+            tx: tx,
+            shadow: shadow,
+            offset: offset,
+            size: size,
+            count: 1,
+            writable: writable,
+        });
+        maps.ptr_to_device.insert(ptr as usize, dev);
+        maps.outstanding.insert(ptr as usize, (offset, size, writable));
 
         ptr
     }
 
+    // CL_INVALID_OPERATION territory: the spec forbids a new writable map (CL_MAP_WRITE or
+    // CL_MAP_WRITE_INVALIDATE_REGION) from overlapping any region that's still mapped, whether
+    // that existing mapping is itself writable or not -- a writer can't be let loose on bytes a
+    // reader somewhere else still expects to be stable either.
+    pub fn has_writable_overlap(&self, offset: usize, size: usize, writable: bool) -> bool {
+        self.maps.lock().unwrap().outstanding.values().any(|&(o, s, w)| {
+            (writable || w) && offset < o + s && o < offset + size
+        })
+    }
+
     pub fn unmap(&self, ptr: *mut c_void) -> bool {
-        self.maps.lock().unwrap().remove(&ptr).is_some()
+        // `map` never created a transfer for these in the first place; just report whether `ptr`
+        // is one of ours.
+        if bit_check(self.flags, CL_MEM_USE_HOST_PTR) {
+            let base = self.host_ptr as usize;
+            let p = ptr as usize;
+            if p < base || p >= base + self.size {
+                return false;
+            }
+            self.maps.lock().unwrap().outstanding.remove(&(ptr as usize));
+            return true;
+        }
+
+        let mut maps = self.maps.lock().unwrap();
+        let (offset, size, _) = match maps.outstanding.remove(&(ptr as usize)) {
+            Some(v) => v,
+            None => return false,
+        };
+
+        let dev = match maps.ptr_to_device.remove(&(ptr as usize)) {
+            Some(dev) => dev,
+            None => return false,
+        };
+
+        // Find which of this device's live `Mapping`s this pointer's region was coalesced into --
+        // the same containment test `map` used to coalesce it in the first place.
+        let mappings = maps.by_device.get_mut(&dev).unwrap();
+        let idx = mappings
+            .iter()
+            .position(|m| offset >= m.offset && offset + size <= m.offset + m.size)
+            .unwrap();
+        mappings[idx].count -= 1;
+
+        if mappings[idx].count == 0 {
+            let m = mappings.remove(idx);
+            if mappings.is_empty() {
+                maps.by_device.remove(&dev);
+            }
+            if let Some(shadow) = &m.shadow {
+                if m.writable {
+                    let res = self.res.as_ref().unwrap().get(&dev).unwrap();
+                    let pipe = self.find_device(dev).screen().create_context().unwrap();
+                    pipe.resource_copy_region(
+                        shadow,
+                        0,
+                        res,
+                        m.offset.try_into().unwrap(),
+                        m.size.try_into().unwrap(),
+                    );
+                }
+            }
+        }
+
+        true
     }
 }
 
@@ -1,5 +1,6 @@
 extern crate mesa_rust;
 extern crate mesa_rust_gen;
+extern crate mesa_rust_util;
 extern crate rusticl_opencl_gen;
 
 use crate::api::icd::*;
@@ -10,17 +11,23 @@ use crate::impl_cl_type_trait;
 use self::mesa_rust::compiler::clc::*;
 use self::mesa_rust::compiler::nir::*;
 use self::mesa_rust_gen::*;
+use self::mesa_rust_util::disk_cache::*;
 use self::rusticl_opencl_gen::*;
 
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::ffi::CString;
+use std::hash::Hash;
+use std::hash::Hasher;
 use std::mem::size_of;
+use std::os::raw::c_void;
 use std::ptr;
 use std::slice;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
+use std::sync::Once;
 
 const BIN_HEADER_SIZE_V1: usize =
     // 1. format version
@@ -32,28 +39,136 @@ const BIN_HEADER_SIZE_V1: usize =
 
 const BIN_HEADER_SIZE: usize = BIN_HEADER_SIZE_V1;
 
+// V2 additionally stores, after the spirv section, one lowered `NirShader` blob per kernel so
+// `clCreateKernel` on a binary-loaded program can skip re-running `to_nir`. Those blobs are only
+// trusted when `build_id` matches this exact driver build (see `build_id` below); a stale or
+// foreign build just falls back to recompiling from the embedded spirv, same as V1.
+const BIN_HEADER_SIZE_V2: usize =
+    // 1. format version
+    size_of::<u32>() +
+    // 2. spirv len
+    size_of::<u32>() +
+    // 3. binary_type
+    size_of::<cl_program_binary_type>() +
+    // 4. build id
+    size_of::<u64>() +
+    // 5. number of cached kernel nir blobs that follow the spirv section
+    size_of::<u32>();
+
 #[repr(C)]
 pub struct Program {
     pub base: CLObjectBase<CL_INVALID_PROGRAM>,
     pub context: Arc<Context>,
     pub devs: Vec<Arc<Device>>,
     pub src: CString,
-    build: Mutex<ProgramBuild>,
+    // Set instead of `src` for a `clCreateProgramWithIL` program: the module is already SPIR-V,
+    // shared across all devices (unlike the per-device `ProgramDevBuild::spirv`, which only holds
+    // the post-link/post-compile result), and only gets parsed/lowered at `build`/`compile` time.
+    il: Option<spirv::SPIRVBin>,
+    // `clSetProgramSpecializationConstant` values, applied when `il` is lowered to NIR. Only
+    // meaningful for an IL-created program; a CLC-source program has no spec constants to set.
+    spec_constants: Mutex<HashMap<u32, Vec<u8>>>,
+    // One independently-lockable entry per device: `clGetProgramBuildInfo` on device A shouldn't
+    // block behind device B's still-running `build`/`compile`, and `build_all` relies on this to
+    // actually run devices concurrently instead of just interleaving under one program-wide lock.
+    builds: HashMap<Arc<Device>, Mutex<ProgramDevBuild>>,
+    // Kernel names are unioned across all devices (see `build`/`link`), so they get their own lock
+    // distinct from any one device's `ProgramDevBuild`.
+    kernels: Mutex<Vec<String>>,
 }
 
 impl_cl_type_trait!(cl_program, Program, CL_INVALID_PROGRAM);
 
-struct ProgramBuild {
-    builds: HashMap<Arc<Device>, ProgramDevBuild>,
-    kernels: Vec<String>,
-}
-
 struct ProgramDevBuild {
     spirv: Option<spirv::SPIRVBin>,
     status: cl_build_status,
     options: String,
     log: String,
     bin_type: cl_program_binary_type,
+    // Per-kernel lowered NIR loaded from a V2 binary (see `from_bins`/`BIN_HEADER_SIZE_V2`).
+    // `nirs` serves out of this instead of re-running `to_nir` whenever a name is present.
+    cached_nirs: HashMap<String, Vec<u8>>,
+}
+
+// Process-wide on-disk cache for the CLC->SPIR-V frontend (see `SPIRVBin::from_clc`). Created
+// once, lazily, on the first build -- seeding it up front would pay the cache's own init cost
+// (opening/creating the cache dir) even for apps that never build a program from source.
+static DISK_CACHE_INIT: Once = Once::new();
+static mut DISK_CACHE: Option<DiskCache> = None;
+
+fn disk_cache() -> &'static Option<DiskCache> {
+    DISK_CACHE_INIT.call_once(|| {
+        // Seeding the cache key with this binary's own function pointers means a rebuilt driver
+        // (different codegen, different frontend behavior) gets a different cache, instead of
+        // handing an updated driver stale SPIR-V compiled by the old one.
+        let ptrs: Vec<*const c_void> = vec![
+            Program::new as *const c_void,
+            Program::build as *const c_void,
+            Program::compile as *const c_void,
+        ];
+        unsafe {
+            DISK_CACHE = DiskCache::new("rusticl", &ptrs);
+        }
+    });
+    unsafe { &DISK_CACHE }
+}
+
+// Guards the cached NIR blobs in a V2 binary (see `BIN_HEADER_SIZE_V2`): a rebuilt driver with
+// different codegen should invalidate blobs written by the old one, but this must come out
+// identical across separate runs of the *same* binary. Function addresses aren't process-invariant
+// under ASLR (the default for PIE binaries), so a previous version of this hashed `Program::new`
+// et al. as raw pointers and, in practice, never matched a binary written by an earlier run --
+// silently falling back to recompiling every time. Hash the on-disk executable's identity (path,
+// size, mtime) instead: stable across runs of the same binary, and changes exactly when the binary
+// itself is rebuilt.
+fn build_id() -> u64 {
+    let mut hasher = DefaultHasher::new();
+    if let Ok(exe) = std::env::current_exe() {
+        exe.hash(&mut hasher);
+        if let Ok(meta) = std::fs::metadata(&exe) {
+            meta.len().hash(&mut hasher);
+            if let Ok(modified) = meta.modified() {
+                modified.hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+// Tells the CLC frontend which optional language features `dev` actually supports, so it can gate
+// builtins and diagnostics on real device capabilities instead of leaving it all to the
+// `-D__OPENCL_VERSION__`/`-U__IMAGE_SUPPORT__` preprocessor hacks in `prepare_options`. Also the
+// basis for `CL_DEVICE_OPENCL_C_FEATURES` once that query exists.
+fn optional_features(dev: &Arc<Device>) -> clc_optional_features {
+    clc_optional_features {
+        fp16: dev.fp16_supported(),
+        fp64: dev.fp64_supported(),
+        int64: true,
+        images: dev.image_supported(),
+        images_read_write: dev.image_supported(),
+        images_mipmap: false,
+        intel_subgroups: false,
+        subgroups: dev.subgroups_supported(),
+        subgroups_shuffle: false,
+        subgroups_shuffle_relative: false,
+        generic_address_space: true,
+        pipes: false,
+        device_enqueue: false,
+        fp32_correctly_rounded_divide_sqrt: false,
+        int64_atomics: dev.int64_atomics_supported(),
+        program_scope_global_variables: true,
+    }
+}
+
+// Devices that only implement an older SPIR-V consumer shouldn't be handed a module versioned
+// above what they advertise support for -- clamp down to `dev.spirv_version` instead of always
+// requesting `CLC_SPIRV_VERSION_MAX`.
+fn spirv_version(dev: &Arc<Device>) -> clc_spirv_version {
+    if dev.spirv_version.0 < clc_spirv_version::CLC_SPIRV_VERSION_MAX.0 {
+        dev.spirv_version
+    } else {
+        clc_spirv_version::CLC_SPIRV_VERSION_MAX
+    }
 }
 
 fn prepare_options(options: &String, dev: &Arc<Device>) -> Vec<CString> {
@@ -90,13 +205,14 @@ impl Program {
             .map(|d| {
                 (
                     d.clone(),
-                    ProgramDevBuild {
+                    Mutex::new(ProgramDevBuild {
                         spirv: None,
                         status: CL_BUILD_NONE,
                         log: String::from(""),
                         options: String::from(""),
                         bin_type: CL_PROGRAM_BINARY_TYPE_NONE,
-                    },
+                        cached_nirs: HashMap::new(),
+                    }),
                 )
             })
             .collect();
@@ -106,13 +222,64 @@ impl Program {
             context: context.clone(),
             devs: devs.clone(),
             src: src,
-            build: Mutex::new(ProgramBuild {
-                builds: builds,
-                kernels: Vec::new(),
-            }),
+            il: None,
+            spec_constants: Mutex::new(HashMap::new()),
+            builds: builds,
+            kernels: Mutex::new(Vec::new()),
         })
     }
 
+    // `clCreateProgramWithIL`: `il` is a complete SPIR-V module, so there's no `src` to build from
+    // -- every device starts out `CL_BUILD_NONE` and gets `il` lowered directly at `build`/
+    // `compile` time instead of running the CLC frontend.
+    pub fn from_il(context: &Arc<Context>, devs: &Vec<Arc<Device>>, il: &[u8]) -> CLResult<Arc<Program>> {
+        let (spirv, _) = spirv::SPIRVBin::from_spirv(il);
+        let spirv = spirv.ok_or(CL_INVALID_VALUE)?;
+
+        let builds = devs
+            .iter()
+            .map(|d| {
+                (
+                    d.clone(),
+                    Mutex::new(ProgramDevBuild {
+                        spirv: None,
+                        status: CL_BUILD_NONE,
+                        log: String::from(""),
+                        options: String::from(""),
+                        bin_type: CL_PROGRAM_BINARY_TYPE_NONE,
+                        cached_nirs: HashMap::new(),
+                    }),
+                )
+            })
+            .collect();
+
+        Ok(Arc::new(Self {
+            base: CLObjectBase::new(),
+            context: context.clone(),
+            devs: devs.clone(),
+            src: CString::new("").unwrap(),
+            il: Some(spirv),
+            spec_constants: Mutex::new(HashMap::new()),
+            builds: builds,
+            kernels: Mutex::new(Vec::new()),
+        }))
+    }
+
+    /// Backs `CL_PROGRAM_IL`: the serialized SPIR-V this program was created from, or an empty
+    /// vec for a program that wasn't created via `clCreateProgramWithIL`.
+    pub fn il(&self) -> Vec<u8> {
+        self.il.as_ref().map_or(Vec::new(), |il| il.to_bin())
+    }
+
+    /// `clSetProgramSpecializationConstant`. Only meaningful for an IL-created program; applied
+    /// the next time a kernel's NIR is created from the built module (`nirs`/`kernel_nirs`).
+    pub fn set_spec_constant(&self, spec_id: u32, bytes: &[u8]) {
+        self.spec_constants
+            .lock()
+            .unwrap()
+            .insert(spec_id, bytes.to_vec());
+    }
+
     pub fn from_bins(
         context: Arc<Context>,
         devs: Vec<Arc<Device>>,
@@ -125,6 +292,7 @@ impl Program {
             let mut ptr = b.as_ptr();
             let bin_type;
             let spirv;
+            let mut cached_nirs = HashMap::new();
 
             unsafe {
                 // 1. version
@@ -149,6 +317,55 @@ impl Program {
                             bin_type == CL_PROGRAM_BINARY_TYPE_EXECUTABLE,
                         ));
                     }
+                    2 => {
+                        // 2. size of the spirv
+                        let spirv_size = ptr.cast::<u32>().read();
+                        ptr = ptr.add(size_of::<u32>());
+
+                        // 3. binary_type
+                        bin_type = ptr.cast::<cl_program_binary_type>().read();
+                        ptr = ptr.add(size_of::<cl_program_binary_type>());
+
+                        // 4. build id guarding the cached nir blobs below
+                        let bin_build_id = ptr.cast::<u64>().read();
+                        ptr = ptr.add(size_of::<u64>());
+
+                        // 5. number of cached kernel nir blobs
+                        let num_kernels = ptr.cast::<u32>().read();
+                        ptr = ptr.add(size_of::<u32>());
+
+                        assert!(b.as_ptr().add(BIN_HEADER_SIZE_V2) == ptr);
+
+                        // 6. the spirv
+                        spirv = Some(spirv::SPIRVBin::from_bin(
+                            slice::from_raw_parts(ptr, spirv_size as usize),
+                            bin_type == CL_PROGRAM_BINARY_TYPE_EXECUTABLE,
+                        ));
+                        ptr = ptr.add(spirv_size as usize);
+
+                        // 7. one (name, nir) pair per cached kernel -- only trusted when this
+                        // exact driver build wrote them, otherwise `nirs` just falls back to
+                        // relowering from the spirv above.
+                        for _ in 0..num_kernels {
+                            let name_len = ptr.cast::<u32>().read();
+                            ptr = ptr.add(size_of::<u32>());
+                            let name = String::from_utf8_lossy(slice::from_raw_parts(
+                                ptr,
+                                name_len as usize,
+                            ))
+                            .into_owned();
+                            ptr = ptr.add(name_len as usize);
+
+                            let nir_len = ptr.cast::<u32>().read();
+                            ptr = ptr.add(size_of::<u32>());
+                            let nir = slice::from_raw_parts(ptr, nir_len as usize).to_vec();
+                            ptr = ptr.add(nir_len as usize);
+
+                            if bin_build_id == build_id() {
+                                cached_nirs.insert(name, nir);
+                            }
+                        }
+                    }
                     _ => panic!("unknown version"),
                 }
             }
@@ -161,13 +378,14 @@ impl Program {
 
             builds.insert(
                 d.clone(),
-                ProgramDevBuild {
+                Mutex::new(ProgramDevBuild {
                     spirv: spirv,
                     status: CL_BUILD_SUCCESS as cl_build_status,
                     log: String::from(""),
                     options: String::from(""),
                     bin_type: bin_type,
-                },
+                    cached_nirs: cached_nirs,
+                }),
             );
         }
 
@@ -176,61 +394,94 @@ impl Program {
             context: context,
             devs: devs,
             src: CString::new("").unwrap(),
-            build: Mutex::new(ProgramBuild {
-                builds: builds,
-                kernels: kernels.into_iter().collect(),
-            }),
+            il: None,
+            spec_constants: Mutex::new(HashMap::new()),
+            builds: builds,
+            kernels: Mutex::new(kernels.into_iter().collect()),
         })
     }
 
-    fn build_info(&self) -> MutexGuard<ProgramBuild> {
-        self.build.lock().unwrap()
-    }
-
-    fn dev_build_info<'a>(
-        l: &'a mut MutexGuard<ProgramBuild>,
-        dev: &Arc<Device>,
-    ) -> &'a mut ProgramDevBuild {
-        l.builds.get_mut(dev).unwrap()
+    fn dev_build(&self, dev: &Arc<Device>) -> MutexGuard<ProgramDevBuild> {
+        self.builds.get(dev).unwrap().lock().unwrap()
     }
 
     pub fn status(&self, dev: &Arc<Device>) -> cl_build_status {
-        Self::dev_build_info(&mut self.build_info(), dev).status
+        self.dev_build(dev).status
     }
 
     pub fn log(&self, dev: &Arc<Device>) -> String {
-        Self::dev_build_info(&mut self.build_info(), dev)
-            .log
-            .clone()
+        self.dev_build(dev).log.clone()
     }
 
     pub fn bin_type(&self, dev: &Arc<Device>) -> cl_program_binary_type {
-        Self::dev_build_info(&mut self.build_info(), dev).bin_type
+        self.dev_build(dev).bin_type
     }
 
     pub fn options(&self, dev: &Arc<Device>) -> String {
-        Self::dev_build_info(&mut self.build_info(), dev)
-            .options
-            .clone()
+        self.dev_build(dev).options.clone()
     }
 
-    // we need to precalculate the size
-    pub fn bin_sizes(&self) -> Vec<usize> {
-        let mut lock = self.build_info();
-        let mut res = Vec::new();
-        for d in &self.devs {
-            let info = Self::dev_build_info(&mut lock, d);
+    // Runs `to_nir` for every kernel of this already-built device, for embedding in a V2 binary
+    // (see `binaries`/`bin_sizes`). Empty when the device hasn't built successfully -- a V2
+    // binary for a failed build just carries the spirv, same as V1.
+    fn kernel_nirs(&self, d: &Arc<Device>, info: &ProgramDevBuild) -> Vec<(String, Vec<u8>)> {
+        if info.status != CL_BUILD_SUCCESS as cl_build_status {
+            return Vec::new();
+        }
 
-            res.push(
-                info.spirv
+        let spec_constants = self.spec_constants.lock().unwrap();
+        self.kernels()
+            .into_iter()
+            .map(|name| {
+                let nir = info
+                    .spirv
                     .as_ref()
-                    .map_or(0, |s| s.to_bin().len() + BIN_HEADER_SIZE),
-            );
-        }
-        res
+                    .unwrap()
+                    .to_nir(
+                        &name,
+                        d.screen
+                            .nir_shader_compiler_options(pipe_shader_type::PIPE_SHADER_COMPUTE),
+                        &d.lib_clc,
+                        &spec_constants,
+                    )
+                    .unwrap()
+                    .serialize();
+                (name, nir)
+            })
+            .collect()
+    }
+
+    // we need to precalculate the size. `include_nir` must match what the matching `binaries`
+    // call will be asked to emit -- it picks V1 vs V2 sizing the same way `binaries` picks the
+    // format to actually write.
+    pub fn bin_sizes(&self, include_nir: bool) -> Vec<usize> {
+        self.devs
+            .iter()
+            .map(|d| {
+                let info = self.dev_build(d);
+                info.spirv.as_ref().map_or(0, |s| {
+                    let spirv_len = s.to_bin().len();
+                    if !include_nir {
+                        return spirv_len + BIN_HEADER_SIZE;
+                    }
+
+                    let kernels_size: usize = self
+                        .kernel_nirs(d, &info)
+                        .iter()
+                        .map(|(name, nir)| {
+                            size_of::<u32>() + name.len() + size_of::<u32>() + nir.len()
+                        })
+                        .sum();
+                    spirv_len + BIN_HEADER_SIZE_V2 + kernels_size
+                })
+            })
+            .collect()
     }
 
-    pub fn binaries(&self, vals: &[u8]) -> Vec<*mut u8> {
+    // `include_nir` emits a V2 binary that additionally caches each kernel's lowered NIR (see
+    // `BIN_HEADER_SIZE_V2`), letting a later `clCreateProgramWithBinary` skip `to_nir` entirely
+    // when `from_bins` loads it back on the same driver build. `false` keeps the plain V1 format.
+    pub fn binaries(&self, vals: &[u8], include_nir: bool) -> Vec<*mut u8> {
         // if the application didn't provide any pointers, just return the length of devices
         if vals.is_empty() {
             return vec![std::ptr::null_mut(); self.devs.len()];
@@ -245,15 +496,36 @@ impl Program {
             slice::from_raw_parts(vals.as_ptr().cast(), vals.len() / size_of::<*mut u8>())
         };
 
-        let mut lock = self.build_info();
         for (i, d) in self.devs.iter().enumerate() {
             let mut ptr = ptrs[i];
-            let info = Self::dev_build_info(&mut lock, d);
+            let info = self.dev_build(d);
             let spirv = info.spirv.as_ref().unwrap().to_bin();
 
+            if !include_nir {
+                unsafe {
+                    // 1. binary format version
+                    ptr.cast::<u32>().write(1);
+                    ptr = ptr.add(size_of::<u32>());
+
+                    // 2. size of the spirv
+                    ptr.cast::<u32>().write(spirv.len() as u32);
+                    ptr = ptr.add(size_of::<u32>());
+
+                    // 3. binary_type
+                    ptr.cast::<cl_program_binary_type>().write(info.bin_type);
+                    ptr = ptr.add(size_of::<cl_program_binary_type>());
+
+                    // 4. the spirv
+                    assert!(ptrs[i].add(BIN_HEADER_SIZE) == ptr);
+                    ptr::copy_nonoverlapping(spirv.as_ptr(), ptr, spirv.len());
+                }
+                continue;
+            }
+
+            let kernel_nirs = self.kernel_nirs(d, &info);
             unsafe {
                 // 1. binary format version
-                ptr.cast::<u32>().write(1);
+                ptr.cast::<u32>().write(2);
                 ptr = ptr.add(size_of::<u32>());
 
                 // 2. size of the spirv
@@ -264,9 +536,32 @@ impl Program {
                 ptr.cast::<cl_program_binary_type>().write(info.bin_type);
                 ptr = ptr.add(size_of::<cl_program_binary_type>());
 
-                // 4. the spirv
-                assert!(ptrs[i].add(BIN_HEADER_SIZE) == ptr);
+                // 4. build id
+                ptr.cast::<u64>().write(build_id());
+                ptr = ptr.add(size_of::<u64>());
+
+                // 5. number of cached kernel nir blobs
+                ptr.cast::<u32>().write(kernel_nirs.len() as u32);
+                ptr = ptr.add(size_of::<u32>());
+
+                // 6. the spirv
+                assert!(ptrs[i].add(BIN_HEADER_SIZE_V2) == ptr);
                 ptr::copy_nonoverlapping(spirv.as_ptr(), ptr, spirv.len());
+                ptr = ptr.add(spirv.len());
+
+                // 7. one (name, nir) pair per kernel
+                for (name, nir) in &kernel_nirs {
+                    let name_bytes = name.as_bytes();
+                    ptr.cast::<u32>().write(name_bytes.len() as u32);
+                    ptr = ptr.add(size_of::<u32>());
+                    ptr::copy_nonoverlapping(name_bytes.as_ptr(), ptr, name_bytes.len());
+                    ptr = ptr.add(name_bytes.len());
+
+                    ptr.cast::<u32>().write(nir.len() as u32);
+                    ptr = ptr.add(size_of::<u32>());
+                    ptr::copy_nonoverlapping(nir.as_ptr(), ptr, nir.len());
+                    ptr = ptr.add(nir.len());
+                }
             }
         }
 
@@ -274,29 +569,68 @@ impl Program {
     }
 
     pub fn args(&self, dev: &Arc<Device>, kernel: &String) -> Vec<spirv::SPIRVKernelArg> {
-        Self::dev_build_info(&mut self.build_info(), dev)
-            .spirv
-            .as_ref()
-            .unwrap()
-            .args(kernel)
+        self.dev_build(dev).spirv.as_ref().unwrap().args(kernel)
     }
 
     pub fn kernels(&self) -> Vec<String> {
-        self.build_info().kernels.clone()
+        self.kernels.lock().unwrap().clone()
+    }
+
+    fn add_kernels(&self, mut new_kernels: Vec<String>) {
+        let mut kernels = self.kernels.lock().unwrap();
+        for k in new_kernels.drain(..) {
+            if !kernels.contains(&k) {
+                kernels.push(k);
+            }
+        }
     }
 
+    // `clCreateProgramWithIL`: there's no frontend to invoke, just a per-device copy of the
+    // already-parsed module (round-tripped through `to_bin`/`from_bin` since `SPIRVBin` isn't
+    // `Clone` -- each device's `ProgramDevBuild` needs to own one independently of `self.il` and
+    // of every other device). Only locks this device's entry, so building device A's IL doesn't
+    // block a concurrent `clGetProgramBuildInfo`/`build` on device B.
+    fn build_il(&self, il: &spirv::SPIRVBin, dev: &Arc<Device>) -> bool {
+        let mut d = self.dev_build(dev);
+        let spirv = spirv::SPIRVBin::from_bin(&il.to_bin(), true);
+
+        let kernels = spirv.kernels();
+        d.spirv = Some(spirv);
+        d.status = CL_BUILD_SUCCESS as cl_build_status;
+        d.bin_type = CL_PROGRAM_BINARY_TYPE_EXECUTABLE;
+        d.log = String::new();
+        drop(d);
+        self.add_kernels(kernels);
+        true
+    }
+
+    // Only ever touches `dev`'s own `ProgramDevBuild` lock (plus the separate `kernels` lock, held
+    // briefly at the end) -- concurrent `build`/`compile` calls for different devices of the same
+    // program (see `build_all`) don't serialize against each other.
     pub fn build(&self, dev: &Arc<Device>, options: String) -> bool {
+        if let Some(il) = &self.il {
+            return self.build_il(il, dev);
+        }
+
         // program binary
         if self.src.as_bytes().len() == 0 {
             return true;
         }
 
-        let mut info = self.build_info();
-        let d = Self::dev_build_info(&mut info, dev);
+        let mut d = self.dev_build(dev);
         let lib = options.contains("-create-library");
 
         let args = prepare_options(&options, dev);
-        let (spirv, log) = spirv::SPIRVBin::from_clc(&self.src, &args, &Vec::new());
+        let features = optional_features(dev);
+        let (spirv, log) = spirv::SPIRVBin::from_clc(
+            &self.src,
+            &args,
+            &Vec::new(),
+            &features,
+            disk_cache(),
+            spirv_version(dev),
+            &[],
+        );
 
         d.log = log;
         d.options = options;
@@ -317,8 +651,9 @@ impl Program {
                 CL_PROGRAM_BINARY_TYPE_EXECUTABLE
             };
             d.status = CL_BUILD_SUCCESS as cl_build_status;
-            let mut kernels = d.spirv.as_ref().unwrap().kernels();
-            info.kernels.append(&mut kernels);
+            let kernels = d.spirv.as_ref().unwrap().kernels();
+            drop(d);
+            self.add_kernels(kernels);
             true
         } else {
             d.status = CL_BUILD_ERROR;
@@ -326,22 +661,48 @@ impl Program {
         }
     }
 
+    // Builds every device in `devs` concurrently instead of one at a time: each `build` call only
+    // takes that device's own lock, so there's no cross-device serialization left to avoid beyond
+    // spawning the calls themselves.
+    pub fn build_all(&self, devs: &Vec<Arc<Device>>, options: &str) -> bool {
+        std::thread::scope(|s| {
+            devs.iter()
+                .map(|d| s.spawn(|| self.build(d, options.to_string())))
+                .collect::<Vec<_>>()
+                .into_iter()
+                .map(|h| h.join().unwrap())
+                .fold(true, |a, b| a && b)
+        })
+    }
+
     pub fn compile(
         &self,
         dev: &Arc<Device>,
         options: String,
         headers: &Vec<spirv::CLCHeader>,
     ) -> bool {
+        if let Some(il) = &self.il {
+            return self.build_il(il, dev);
+        }
+
         // program binary
         if self.src.as_bytes().len() == 0 {
             return true;
         }
 
-        let mut info = self.build_info();
-        let d = Self::dev_build_info(&mut info, dev);
+        let mut d = self.dev_build(dev);
         let args = prepare_options(&options, dev);
 
-        let (spirv, log) = spirv::SPIRVBin::from_clc(&self.src, &args, headers);
+        let features = optional_features(dev);
+        let (spirv, log) = spirv::SPIRVBin::from_clc(
+            &self.src,
+            &args,
+            headers,
+            &features,
+            disk_cache(),
+            spirv_version(dev),
+            &[],
+        );
 
         d.spirv = spirv;
         d.log = log;
@@ -366,13 +727,15 @@ impl Program {
         let devs: Vec<Arc<Device>> = devs.iter().map(|d| (*d).clone()).collect();
         let mut builds = HashMap::new();
         let mut kernels = HashSet::new();
-        let mut locks: Vec<_> = progs.iter().map(|p| p.build_info()).collect();
         let lib = options.contains("-create-library");
 
         for d in &devs {
+            // Only holds each input program's lock for `d` long enough to read its spirv; devices
+            // other than `d` stay unlocked for the other programs, same as elsewhere in this file.
+            let locks: Vec<_> = progs.iter().map(|p| p.dev_build(d)).collect();
             let bins = locks
-                .iter_mut()
-                .map(|l| Self::dev_build_info(l, d).spirv.as_ref().unwrap())
+                .iter()
+                .map(|l| l.spirv.as_ref().unwrap())
                 .collect();
 
             let (spirv, log) = spirv::SPIRVBin::link(&bins, lib);
@@ -396,13 +759,14 @@ impl Program {
 
             builds.insert(
                 d.clone(),
-                ProgramDevBuild {
+                Mutex::new(ProgramDevBuild {
                     spirv: spirv,
                     status: status,
                     log: log,
                     options: String::from(""),
                     bin_type: bin_type,
-                },
+                    cached_nirs: HashMap::new(),
+                }),
             );
         }
 
@@ -411,32 +775,44 @@ impl Program {
             context: context.clone(),
             devs: devs,
             src: CString::new("").unwrap(),
-            build: Mutex::new(ProgramBuild {
-                builds: builds,
-                kernels: kernels.into_iter().collect(),
-            }),
+            il: None,
+            spec_constants: Mutex::new(HashMap::new()),
+            builds: builds,
+            kernels: Mutex::new(kernels.into_iter().collect()),
         })
     }
 
+    // Each device's lock is held only while that device's own NIR is looked up or lowered, so
+    // lowering device A's NIR doesn't block a concurrent query or build on device B.
     pub fn nirs(&self, kernel: &String) -> HashMap<Arc<Device>, NirShader> {
-        let mut lock = self.build_info();
         let mut res = HashMap::new();
+        let spec_constants = self.spec_constants.lock().unwrap();
         for d in &self.devs {
-            let info = Self::dev_build_info(&mut lock, d);
+            let info = self.dev_build(d);
             if info.status != CL_BUILD_SUCCESS as cl_build_status {
                 continue;
             }
+
+            // A V2 binary may already carry this kernel's lowered NIR (see `from_bins`); reuse it
+            // instead of relowering from spirv when present.
             let nir = info
-                .spirv
-                .as_ref()
-                .unwrap()
-                .to_nir(
-                    kernel,
-                    d.screen
-                        .nir_shader_compiler_options(pipe_shader_type::PIPE_SHADER_COMPUTE),
-                    &d.lib_clc,
-                )
-                .unwrap();
+                .cached_nirs
+                .get(kernel)
+                .and_then(|cached| NirShader::deserialize(cached));
+
+            let nir = nir.unwrap_or_else(|| {
+                info.spirv
+                    .as_ref()
+                    .unwrap()
+                    .to_nir(
+                        kernel,
+                        d.screen
+                            .nir_shader_compiler_options(pipe_shader_type::PIPE_SHADER_COMPUTE),
+                        &d.lib_clc,
+                        &spec_constants,
+                    )
+                    .unwrap()
+            });
             res.insert(d.clone(), nir);
         }
         res
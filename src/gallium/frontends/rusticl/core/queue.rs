@@ -2,6 +2,7 @@ extern crate mesa_rust;
 extern crate rusticl_opencl_gen;
 
 use crate::api::icd::*;
+use crate::api::util::bit_check;
 use crate::core::context::*;
 use crate::core::device::*;
 use crate::core::event::*;
@@ -10,8 +11,13 @@ use crate::impl_cl_type_trait;
 use self::mesa_rust::pipe::context::*;
 use self::rusticl_opencl_gen::*;
 
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc;
 use std::sync::Arc;
+use std::sync::Condvar;
 use std::sync::Mutex;
 use std::thread;
 use std::thread::JoinHandle;
@@ -30,6 +36,112 @@ pub struct Queue {
 
 impl_cl_type_trait!(cl_command_queue, Queue, CL_INVALID_COMMAND_QUEUE);
 
+// drains every dep of `e`, failing it if any dependency failed, otherwise running it.
+fn run_event(e: &Arc<Event>) {
+    let err = e.deps.iter().map(|d| d.wait()).find(|s| *s < 0);
+    if let Some(err) = err {
+        // if a dependency failed, fail this event as well
+        e.set_user_status(err);
+    } else if bit_check(e.queue.props, CL_QUEUE_PROFILING_ENABLE) {
+        // bracket the actual work so CL_PROFILING_COMMAND_START/END have something to report;
+        // skip the extra timestamp query entirely on non-profiling queues.
+        e.set_start(e.queue.context().timestamp_ns());
+        e.call();
+        e.set_end(e.queue.context().timestamp_ns());
+    } else {
+        e.call();
+    }
+}
+
+// Dispatches a batch of out-of-order events over a small pool of worker threads, honoring the
+// dependency DAG: an event only becomes "ready" once every dep it shares with this batch has
+// finished, so a worker is never handed an event that could block on a sibling still sitting
+// unscheduled in the ready queue. Deps from an earlier batch or another queue aren't tracked here
+// -- they fall through to `run_event`'s own blocking `dep.wait()`, same as the in-order path,
+// since nothing in this pool could be the one to unblock them anyway.
+fn run_out_of_order(new_events: &[Arc<Event>]) {
+    let total = new_events.len();
+    let idx_of: HashMap<*const Event, usize> = new_events
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (Arc::as_ptr(e), i))
+        .collect();
+
+    let remaining: Vec<AtomicUsize> = new_events
+        .iter()
+        .map(|e| {
+            AtomicUsize::new(
+                e.deps
+                    .iter()
+                    .filter(|d| idx_of.contains_key(&Arc::as_ptr(d)))
+                    .count(),
+            )
+        })
+        .collect();
+
+    let mut dependents: Vec<Vec<usize>> = vec![Vec::new(); total];
+    for (i, e) in new_events.iter().enumerate() {
+        for d in &e.deps {
+            if let Some(&j) = idx_of.get(&Arc::as_ptr(d)) {
+                dependents[j].push(i);
+            }
+        }
+    }
+
+    let ready = Mutex::new(
+        (0..total)
+            .filter(|&i| remaining[i].load(Ordering::Relaxed) == 0)
+            .collect::<VecDeque<usize>>(),
+    );
+    let ready_cv = Condvar::new();
+    let dispatched = AtomicUsize::new(0);
+
+    // one OS thread per available core is plenty; the ready-set above is what keeps the DAG
+    // correctly ordered, not the pool size.
+    let workers = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(total);
+
+    thread::scope(|s| {
+        for _ in 0..workers {
+            s.spawn(|| loop {
+                let idx = {
+                    let mut q = ready.lock().unwrap();
+                    loop {
+                        if let Some(idx) = q.pop_front() {
+                            break Some(idx);
+                        }
+                        if dispatched.load(Ordering::Acquire) >= total {
+                            break None;
+                        }
+                        q = ready_cv.wait(q).unwrap();
+                    }
+                };
+                let Some(idx) = idx else {
+                    break;
+                };
+
+                run_event(&new_events[idx]);
+                dispatched.fetch_add(1, Ordering::AcqRel);
+
+                let mut newly_ready = Vec::new();
+                for &dependent in &dependents[idx] {
+                    if remaining[dependent].fetch_sub(1, Ordering::AcqRel) == 1 {
+                        newly_ready.push(dependent);
+                    }
+                }
+
+                if !newly_ready.is_empty() || dispatched.load(Ordering::Acquire) >= total {
+                    let mut q = ready.lock().unwrap();
+                    q.extend(newly_ready);
+                    ready_cv.notify_all();
+                }
+            });
+        }
+    });
+}
+
 impl Queue {
     pub fn new(
         context: &Arc<Context>,
@@ -58,15 +170,15 @@ impl Queue {
                             break;
                         }
                         let new_events = r.unwrap();
-                        for e in &new_events {
-                            // all events should be processed, but we might have to wait on user
-                            // events to happen
-                            let err = e.deps.iter().map(|e| e.wait()).find(|s| *s < 0);
-                            if let Some(err) = err {
-                                // if a dependency failed, fail this event as well
-                                e.set_user_status(err);
-                            } else {
-                                e.call();
+                        let out_of_order =
+                            bit_check(props, CL_QUEUE_OUT_OF_ORDER_EXEC_MODE_ENABLE);
+                        if out_of_order && new_events.len() > 1 {
+                            run_out_of_order(&new_events);
+                        } else {
+                            // in-order queues are a degenerate DAG: just run the batch in the
+                            // order it was submitted.
+                            for e in &new_events {
+                                run_event(e);
                             }
                         }
                         for e in new_events {
@@ -84,11 +196,36 @@ impl Queue {
     }
 
     pub fn queue(&self, e: &Arc<Event>) {
+        // CL_PROFILING_COMMAND_QUEUED: when the command was queued by the host.
+        if bit_check(self.props, CL_QUEUE_PROFILING_ENABLE) {
+            e.set_queued(self.pipe.timestamp_ns());
+        }
         self.pending.lock().unwrap().push(e.clone());
     }
 
+    // A barrier must wait on every command already submitted to this queue and become a
+    // dependency of every command submitted after it. The worker thread only ever looks at the
+    // next batch once the current one has fully completed (see the wait loop of the worker
+    // thread spawned in `Queue::new`), so flushing
+    // whatever is pending into its own batch before queuing the barrier -- and flushing the
+    // barrier on its own right after -- gives us both guarantees without having to mutate any
+    // already-constructed `Event`'s dependency list.
+    pub fn queue_barrier(&self, e: &Arc<Event>) -> CLResult<()> {
+        self.flush(false)?;
+        self.pending.lock().unwrap().push(e.clone());
+        self.flush(false)
+    }
+
     pub fn flush(&self, wait: bool) -> CLResult<()> {
         let mut p = self.pending.lock().unwrap();
+        // CL_PROFILING_COMMAND_SUBMIT: when the host hands the command off to the device, i.e.
+        // right as it leaves `pending` for the worker thread.
+        if bit_check(self.props, CL_QUEUE_PROFILING_ENABLE) {
+            let now = self.pipe.timestamp_ns();
+            for e in p.iter() {
+                e.set_submit(now);
+            }
+        }
         let last = p.last().map(|e| e.clone());
         // This should never ever error, but if it does return an error
         self.chan_in
@@ -2,9 +2,14 @@ extern crate mesa_rust_gen;
 extern crate mesa_rust_util;
 
 use self::mesa_rust_gen::*;
+use self::mesa_rust_util::disk_cache::*;
 use self::mesa_rust_util::string::*;
 
+use std::collections::hash_map::DefaultHasher;
 use std::ffi::CString;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::mem::size_of;
 use std::os::raw::c_char;
 use std::os::raw::c_void;
 use std::ptr;
@@ -37,11 +42,48 @@ unsafe extern "C" fn msg_callback(data: *mut std::ffi::c_void, msg: *const c_cha
 }
 
 impl SPIRVBin {
+    // `cache` is the process-wide on-disk CLC->SPIR-V cache (see `disk_cache` in `core::program`); the key
+    // is a hash of `source` plus the fully-expanded `args` (which already bakes in the device's
+    // `-cl-std=CLx.y`, so it doesn't need to be hashed separately). A cache hit skips the frontend
+    // entirely and just deserializes the stored binary.
     pub fn from_clc(
         source: &CString,
         args: &Vec<CString>,
         headers: &Vec<CLCHeader>,
+        features: &clc_optional_features,
+        cache: &Option<DiskCache>,
+        spirv_version: clc_spirv_version,
+        allowed_extensions: &[CString],
     ) -> (Option<Self>, String) {
+        let key = cache.as_ref().map(|cache| {
+            let mut hasher = DefaultHasher::new();
+            source.as_bytes().hash(&mut hasher);
+            for a in args {
+                a.as_bytes().hash(&mut hasher);
+            }
+            // `clc_optional_features` has no `Hash` impl of its own, so hash its raw bytes --
+            // different negotiated features can legally produce different SPIR-V for identical
+            // source/args (e.g. fp16 builtins only lowered when the device actually supports it).
+            unsafe {
+                slice::from_raw_parts(
+                    (features as *const clc_optional_features) as *const u8,
+                    size_of::<clc_optional_features>(),
+                )
+            }
+            .hash(&mut hasher);
+            (spirv_version.0 as u32).hash(&mut hasher);
+            for e in allowed_extensions {
+                e.as_bytes().hash(&mut hasher);
+            }
+            cache.gen_key(&hasher.finish().to_ne_bytes())
+        });
+
+        if let (Some(cache), Some(key)) = (cache, &key) {
+            if let Some(bin) = cache.get(key) {
+                return (Some(SPIRVBin::from_bin(&bin, true)), String::new());
+            }
+        }
+
         let c_headers: Vec<_> = headers
             .iter()
             .map(|h| clc_named_value {
@@ -52,6 +94,17 @@ impl SPIRVBin {
 
         let c_args: Vec<_> = args.iter().map(|a| a.as_ptr()).collect();
 
+        // A NULL-terminated array of extension name pointers, or null to mean "no restriction" --
+        // matching the `allowed_spirv_extensions` contract this already hardcoded `ptr::null()`
+        // for.
+        let mut c_extensions: Vec<_> = allowed_extensions.iter().map(|e| e.as_ptr()).collect();
+        let allowed_spirv_extensions = if c_extensions.is_empty() {
+            ptr::null()
+        } else {
+            c_extensions.push(ptr::null());
+            c_extensions.as_ptr()
+        };
+
         let args = clc_compile_args {
             headers: c_headers.as_ptr(),
             num_headers: c_headers.len() as u32,
@@ -61,8 +114,9 @@ impl SPIRVBin {
             },
             args: c_args.as_ptr(),
             num_args: c_args.len() as u32,
-            spirv_version: clc_spirv_version::CLC_SPIRV_VERSION_MAX,
-            allowed_spirv_extensions: ptr::null(),
+            spirv_version: spirv_version,
+            allowed_spirv_extensions: allowed_spirv_extensions,
+            features: *features,
         };
         let mut msgs: Vec<String> = Vec::new();
         let logger = clc_logger {
@@ -82,6 +136,35 @@ impl SPIRVBin {
         } else {
             None
         };
+
+        if let (Some(cache), Some(key), Some(bin)) = (cache, &key, &res) {
+            cache.put(key, &bin.to_bin());
+        }
+
+        (res, msgs.join("\n"))
+    }
+
+    // `clCreateProgramWithIL`: `bytes` is already SPIR-V, so there's no CLC frontend step -- just
+    // parse it for kernel/arg info the same way `link` does for a freshly linked, non-library
+    // module.
+    pub fn from_spirv(bytes: &[u8]) -> (Option<Self>, String) {
+        let mut msgs: Vec<String> = Vec::new();
+        let logger = clc_logger {
+            priv_: &mut msgs as *mut Vec<String> as *mut c_void,
+            error: Some(msg_callback),
+            warning: Some(msg_callback),
+        };
+
+        let mut bin = Self::from_bin(bytes, false);
+        let mut pspirv = clc_parsed_spirv::default();
+        let res = unsafe { clc_parse_spirv(&bin.spirv, &logger, &mut pspirv) };
+
+        let res = if res {
+            bin.info = Some(pspirv);
+            Some(bin)
+        } else {
+            None
+        };
         (res, msgs.join("\n"))
     }
 
@@ -129,6 +212,48 @@ impl SPIRVBin {
         (res, msgs.join("\n"))
     }
 
+    // Copies the `clc_binary`'s `data`/`size` out into an owned buffer so a compiled program can
+    // be persisted (e.g. the on-disk CLC->SPIR-V cache, or `CL_PROGRAM_BINARIES`) independently of
+    // this `SPIRVBin`'s lifetime.
+    pub fn to_bin(&self) -> Vec<u8> {
+        unsafe { slice::from_raw_parts(self.spirv.data.cast(), self.spirv.size) }.to_vec()
+    }
+
+    // Rebuilds a `clc_binary` from bytes previously produced by `to_bin`. `clc_free_spirv` frees
+    // `data` with the C `free`, so the backing allocation has to come from the same allocator --
+    // Rust's global allocator is just `malloc`/`free` on the Linux targets this driver runs on, so
+    // leaking a boxed slice and handing out the raw pointer is safe here.
+    //
+    // When `parse` is set, also re-runs `clc_parse_spirv` to repopulate `info`, which is what
+    // `kernels()`/`args()` read -- needed whenever the result might be queried for kernel info,
+    // as opposed to e.g. `from_spirv`, which parses the copy itself afterwards.
+    pub fn from_bin(bin: &[u8], parse: bool) -> Self {
+        let data = bin.to_vec().into_boxed_slice();
+        let spirv = clc_binary {
+            data: Box::into_raw(data).cast(),
+            size: bin.len(),
+        };
+
+        let info = if parse {
+            let mut msgs: Vec<String> = Vec::new();
+            let logger = clc_logger {
+                priv_: &mut msgs as *mut Vec<String> as *mut c_void,
+                error: Some(msg_callback),
+                warning: Some(msg_callback),
+            };
+            let mut pspirv = clc_parsed_spirv::default();
+            if unsafe { clc_parse_spirv(&spirv, &logger, &mut pspirv) } {
+                Some(pspirv)
+            } else {
+                None
+            }
+        } else {
+            None
+        };
+
+        SPIRVBin { spirv, info }
+    }
+
     fn kernel_infos(&self) -> &[clc_kernel_info] {
         match self.info {
             None => &[],
@@ -16,6 +16,29 @@ use std::sync::Arc;
 pub struct PipeContext {
     pipe: NonNull<pipe_context>,
     screen: Arc<PipeScreen>,
+    // fixed epoch `timestamp_ns` is measured against, captured when this context (and therefore
+    // the `Queue` that owns it) was created, so profiling timestamps are monotonic and comparable
+    // across every event on that queue regardless of wall-clock adjustments.
+    epoch: std::time::Instant,
+}
+
+/// Which direction(s) a `buffer_map`/`texture_map` transfer will be used for, so the driver only
+/// gets the `PIPE_MAP_READ`/`PIPE_MAP_WRITE` bits it actually needs instead of always both.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum RWFlags {
+    R,
+    W,
+    RW,
+}
+
+impl RWFlags {
+    fn to_pipe_map_flags(self) -> pipe_map_flags {
+        match self {
+            RWFlags::R => pipe_map_flags::PIPE_MAP_READ,
+            RWFlags::W => pipe_map_flags::PIPE_MAP_WRITE,
+            RWFlags::RW => pipe_map_flags::PIPE_MAP_READ | pipe_map_flags::PIPE_MAP_WRITE,
+        }
+    }
 }
 
 impl PipeContext {
@@ -23,6 +46,7 @@ impl PipeContext {
         let s = Self {
             pipe: NonNull::new(context)?,
             screen: screen.clone(),
+            epoch: std::time::Instant::now(),
         };
 
         if !has_required_cbs(unsafe { s.pipe.as_ref() }) {
@@ -52,6 +76,26 @@ impl PipeContext {
         }
     }
 
+    pub fn clear_buffer(
+        &self,
+        res: &PipeResource,
+        offset: c_uint,
+        size: c_uint,
+        value: *const c_void,
+        value_size: i32,
+    ) {
+        unsafe {
+            self.pipe.as_ref().clear_buffer.unwrap()(
+                self.pipe.as_ptr(),
+                res.pipe(),
+                offset,
+                size,
+                value,
+                value_size,
+            )
+        }
+    }
+
     pub fn resource_copy_region(
         &self,
         src: &PipeResource,
@@ -88,6 +132,7 @@ impl PipeContext {
         res: &PipeResource,
         offset: i32,
         size: i32,
+        rw: RWFlags,
         block: bool,
     ) -> PipeTransfer {
         let mut b = pipe_box::default();
@@ -98,10 +143,10 @@ impl PipeContext {
         b.height = 1;
         b.depth = 1;
 
-        let flags = match block {
-            false => pipe_map_flags::PIPE_MAP_UNSYNCHRONIZED,
-            true => pipe_map_flags(0),
-        };
+        let mut flags = rw.to_pipe_map_flags();
+        if !block {
+            flags |= pipe_map_flags::PIPE_MAP_UNSYNCHRONIZED;
+        }
 
         let ptr = unsafe {
             self.pipe.as_ref().buffer_map.unwrap()(
@@ -117,6 +162,34 @@ impl PipeContext {
         PipeTransfer::new(out, ptr, self)
     }
 
+    pub fn texture_map(
+        self: &Arc<Self>,
+        res: &PipeResource,
+        bx: &pipe_box,
+        rw: RWFlags,
+        block: bool,
+    ) -> PipeTransfer {
+        let mut out: *mut pipe_transfer = ptr::null_mut();
+
+        let mut flags = rw.to_pipe_map_flags();
+        if !block {
+            flags |= pipe_map_flags::PIPE_MAP_UNSYNCHRONIZED;
+        }
+
+        let ptr = unsafe {
+            self.pipe.as_ref().texture_map.unwrap()(
+                self.pipe.as_ptr(),
+                res.pipe(),
+                0,
+                flags.0,
+                bx,
+                &mut out,
+            )
+        };
+
+        PipeTransfer::new(out, ptr, self)
+    }
+
     pub(super) fn buffer_unmap(&self, tx: *mut pipe_transfer) {
         unsafe { self.pipe.as_ref().buffer_unmap.unwrap()(self.pipe.as_ptr(), tx) };
     }
@@ -155,12 +228,27 @@ impl PipeContext {
         unsafe { self.pipe.as_ref().delete_compute_state.unwrap()(self.pipe.as_ptr(), state) }
     }
 
+    pub fn get_compute_state_info(&self, state: *mut c_void) -> pipe_compute_state_object_info {
+        let mut info = pipe_compute_state_object_info::default();
+        unsafe {
+            self.pipe.as_ref().get_compute_state_info.unwrap()(
+                self.pipe.as_ptr(),
+                state,
+                &mut info,
+            )
+        }
+        info
+    }
+
     pub fn launch_grid(
         &self,
         work_dim: u32,
         block: [u32; 3],
         grid: [u32; 3],
-        grid_base: [u32; 3],
+        // per-dimension invocation count of the last (boundary) work-group, or 0 if it's
+        // full-size; lets the driver mask off the out-of-range tail when the global size isn't an
+        // even multiple of the work-group size (`CL_DEVICE_NON_UNIFORM_WORK_GROUP_SUPPORT`).
+        last_block: [u32; 3],
         input: &[u8],
     ) {
         let info = pipe_grid_info {
@@ -168,9 +256,11 @@ impl PipeContext {
             input: input.as_ptr().cast(),
             work_dim: work_dim,
             block: block,
-            last_block: [0; 3],
+            last_block: last_block,
             grid: grid,
-            grid_base: grid_base,
+            // global work offsets are baked into the kernel input as an internal arg instead, so
+            // the grid itself always starts at the origin.
+            grid_base: [0; 3],
             indirect: ptr::null_mut(),
             indirect_offset: 0,
         };
@@ -209,6 +299,15 @@ impl PipeContext {
         unsafe { self.pipe.as_ref().memory_barrier.unwrap()(self.pipe.as_ptr(), barriers) }
     }
 
+    // TODO: wrap `create_query`/`get_query_result` with `PIPE_QUERY_TIMESTAMP` once a driver that
+    // actually exposes it is plumbed through here; until then fall back to a host monotonic clock,
+    // which is good enough for profiling info's "relative to other events on this device" contract.
+    // Measured against `epoch` rather than a wall-clock origin so timestamps can't regress if the
+    // system clock gets adjusted mid-queue, and stay comparable across every event on this queue.
+    pub fn timestamp_ns(&self) -> u64 {
+        self.epoch.elapsed().as_nanos() as u64
+    }
+
     pub fn flush(&self) -> PipeFence {
         unsafe {
             let mut fence = ptr::null_mut();
@@ -233,11 +332,14 @@ fn has_required_cbs(c: &pipe_context) -> bool {
         && c.buffer_map.is_some()
         && c.buffer_subdata.is_some()
         && c.buffer_unmap.is_some()
+        && c.clear_buffer.is_some()
         && c.create_compute_state.is_some()
         && c.delete_compute_state.is_some()
+        && c.get_compute_state_info.is_some()
         && c.flush.is_some()
         && c.launch_grid.is_some()
         && c.memory_barrier.is_some()
         && c.resource_copy_region.is_some()
         && c.set_global_binding.is_some()
+        && c.texture_map.is_some()
 }
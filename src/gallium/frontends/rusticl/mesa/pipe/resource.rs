@@ -4,6 +4,19 @@ use self::mesa_rust_gen::*;
 
 use std::ptr;
 
+/// Which allocation strategy a `PipeScreen::resource_create_*` call should prefer, so callers that
+/// know how a resource will be used (e.g. `CL_MEM_ALLOC_HOST_PTR`'s host-visible requirement) can
+/// ask for it without hand-rolling pipe bind/usage flags at every call site.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResourceType {
+    /// ordinary device-local allocation; the default for buffers/images without special needs.
+    Normal,
+    /// host-visible, write-combined memory suited for one-shot uploads/downloads (staging).
+    Staging,
+    /// host-visible, cached memory suited for repeated CPU reads, e.g. `CL_MEM_ALLOC_HOST_PTR`.
+    Cached,
+}
+
 pub struct PipeResource {
     pipe: *mut pipe_resource,
 }
@@ -64,6 +77,17 @@ impl PipeResource {
         }
         res
     }
+
+    /// Whether this resource's memory can be mapped directly by the CPU without bouncing through
+    /// a staging shadow resource first. `PIPE_USAGE_STAGING`/`PIPE_USAGE_STREAM` are always
+    /// host-visible (what `ResourceType::Staging`/`Cached` ask `resource_create_*` for); anything
+    /// else may be device-local-only, so callers like `Mem::map` have to fall back to a shadow.
+    pub fn is_host_visible(&self) -> bool {
+        matches!(
+            self.as_ref().usage,
+            pipe_resource_usage::PIPE_USAGE_STAGING | pipe_resource_usage::PIPE_USAGE_STREAM
+        )
+    }
 }
 
 impl Drop for PipeResource {